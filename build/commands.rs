@@ -47,9 +47,14 @@ impl Commands
             mod args;
 
             use Connection;
-            use {{Client, IntoArg, Result}};
+            use {{Client, IntoArg, ResilientFeed, Response, Result, TopN}};
             use slog::Logger;
             use ql2::proto::{{Term, Term_TermType as Type}};
+            use reql_types::BatchStatus;
+            use serde::Serialize;
+            use serde::de::DeserializeOwned;
+            use std::net::SocketAddr;
+            use std::time::Duration;
         "#)
     }
 
@@ -103,25 +108,75 @@ impl Commands
                 /// before the first command you want to log and then deactivating soon after
                 /// the last command you want to log.
                 ///
+                /// The logger is inherited by everything the client does, including connecting,
+                /// running queries and keeping track of the servers in the cluster, so the driver's
+                /// logs end up wherever your application's logs go. Without a logger the driver
+                /// doesn't log anything.
+                ///
                 /// __Example__: Override the default client logger.
                 ///
                 /// ```reql
                 /// # extern crate slog_term;
                 /// # #[macro_use] extern crate slog;
-                /// # use slog::DrainExt;
-                /// # let drain = slog_term::streamer().async().compact().build();
+                /// # use slog::Drain;
+                /// # let plain = slog_term::PlainSyncDecorator::new(std::io::stdout());
+                /// # let drain = slog_term::FullFormat::new(plain).build();
                 /// # let logger = slog::Logger::root(drain.fuse(), o!());
                 /// let r = Client::new().with_logger(logger);
                 /// ```
                 ///
-                /// See [examples/logging.rs] for an example of setting up an [slog](https://docs.rs/slog) `logger`.
+                /// See [examples/map.rs] for an example of setting up an [slog](https://docs.rs/slog) `logger`.
                 ///
-                /// [examples/logging.rs]: https://github.com/rust-rethinkdb/reql/blob/master/examples/logging.rs
+                /// [examples/map.rs]: https://github.com/rust-rethinkdb/reql/blob/master/examples/map.rs
 
                 pub fn with_logger(&self, logger: Logger) -> Client {{
                     util::with_logger(self, logger)
                 }}
 
+                /// Set options to pass to every `run` of commands built from this client
+                ///
+                /// Both global options understood by the server and the driver's own
+                /// options, like `buffer_size`, are accepted. Every command chained from the
+                /// returned client inherits them and options passed to `run` itself take
+                /// precedence over them.
+                ///
+                /// __Example__: Read possibly outdated data and write with soft durability by default.
+                ///
+                /// ```rust,no_run
+                /// # #[macro_use] extern crate reql;
+                /// # use reql::Client;
+                /// # fn main() {{
+                /// let r = Client::new().with_default_run_opts(args!({{read_mode: "outdated", durability: "soft"}}));
+                /// # }}
+                /// ```
+
+                pub fn with_default_run_opts<T: IntoArg>(&self, opts: T) -> Client {{
+                    util::with_default_run_opts(self, opts)
+                }}
+
+                /// Set the database of tables in queries built from this client
+                ///
+                /// This sets the `db` [default run option](struct.Client.html#method.with_default_run_opts)
+                /// and leaves the others as they are. A database given by [db](struct.Client.html#method.db)
+                /// in the query itself or by the `db` option passed to `run` still takes precedence.
+                ///
+                /// __Example__: Query tables in two databases from the same client.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # use reql::Client;
+                /// # fn main() {{
+                /// let r = Client::new().use_db("blog");
+                /// r.table("posts");
+                /// r.db("heroes").table("marvel");
+                /// # }}
+                /// ```
+
+                pub fn use_db(&self, name: &str) -> Client {{
+                    util::use_db(self, name)
+                }}
+
                 /// Specify optional arguments to a ReQL command
                 ///
                 /// Normally, you should use the `args!()` macro to pass arguments to a command that
@@ -146,6 +201,522 @@ impl Commands
                     util::with_args(self, args)
                 }}
 
+                /// Create a new connection to the database server without a Tokio handle
+                ///
+                /// This works just like [connect](struct.Client.html#method.connect) except that the driver
+                /// starts and manages a reactor of its own, so programs that don't otherwise use Tokio
+                /// don't have to create one. The reactor is shut down once the connection is closed.
+                ///
+                /// __Example__: Open a connection using the default host and port.
+                ///
+                /// ```rust,no_run
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # use reql::Client;
+                /// # fn main() {{
+                /// # let r = Client::new();
+                /// let conn = r.connect_sync(args!());
+                /// # }}
+                /// ```
+
+                pub fn connect_sync<T: IntoArg>(&self, args: T) -> Result<Connection> {{
+                    io::connect_sync(self, args)
+                }}
+
+                /// Create a new connection to servers whose addresses are already known
+                ///
+                /// This works just like [connect](struct.Client.html#method.connect) except that the
+                /// addresses are used as they are instead of being looked up, which helps when DNS is
+                /// slow or unreliable. Each server is named after its address, for example
+                /// `10.0.0.1:28015`. The `servers` option can't be combined with this.
+                ///
+                /// __Example__: Connect to two servers by IP address.
+                ///
+                /// ```rust,no_run
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # extern crate tokio_core;
+                /// # use reql::Client;
+                /// # use tokio_core::reactor::Core;
+                /// # fn main() {{
+                /// # let r = Client::new();
+                /// # let core = Core::new().unwrap();
+                /// let addrs = vec!["10.0.0.1:28015".parse().unwrap(), "10.0.0.2:28015".parse().unwrap()];
+                /// let conn = r.connect_addrs(addrs, args!(core.handle()));
+                /// # }}
+                /// ```
+
+                pub fn connect_addrs<T: IntoArg>(&self, addrs: Vec<SocketAddr>, args: T) -> Result<Connection> {{
+                    io::connect_addrs(self, addrs, args)
+                }}
+
+                /// Sort in ascending order
+                ///
+                /// Used with [order_by](struct.Client.html#method.order_by) to sort by a field or an index in
+                /// ascending order. This is the default so it's only needed to make the ordering explicit.
+                ///
+                /// __Example__: Order posts by date, oldest first.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("posts").order_by(args!({{index: r.asc("date")}}));
+                /// # }}
+                /// ```
+
+                pub fn asc<T: IntoArg>(&self, args: T) -> Client {{
+                    util::make_cmd(self, "asc", Some(Type::ASC), Some(args))
+                }}
+
+                /// Sort in descending order
+                ///
+                /// Used with [order_by](struct.Client.html#method.order_by) to sort by a field or an index in
+                /// descending order.
+                ///
+                /// __Example__: Order posts by date, newest first.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("posts").order_by(args!({{index: r.desc("date")}}));
+                /// # }}
+                /// ```
+
+                pub fn desc<T: IntoArg>(&self, args: T) -> Client {{
+                    util::make_cmd(self, "desc", Some(Type::DESC), Some(args))
+                }}
+
+                /// Refer to the current document
+                ///
+                /// Commands like [filter](struct.Client.html#method.filter) and
+                /// [map](struct.Client.html#method.map) accept `r.row()` in place of a closure taking the
+                /// current document. Like in the official drivers, `r.row()` can't be used in nested
+                /// queries since it's not possible to tell which document it refers to. Running such a
+                /// query returns an error so use a closure instead.
+                ///
+                /// __Example__: Get all users who are 18 or older.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("users").filter(r.row().get_field("age").ge(18));
+                /// // is the same as
+                /// r.table("users").filter(args!(|user| user.get_field("age").ge(18)));
+                /// # }}
+                /// ```
+
+                pub fn row(&self) -> Client {{
+                    util::make_cmd::<Client>(self, "row", Some(Type::IMPLICIT_VAR), None)
+                }}
+
+                /// The smallest possible value, for use with [between](struct.Client.html#method.between)
+                ///
+                /// __Example__: Get all users whose age is below 18.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("users").between(args!(r.minval(), 18, {{index: "age"}}));
+                /// # }}
+                /// ```
+
+                pub fn minval(&self) -> Client {{
+                    util::make_cmd::<Client>(self, "minval", Some(Type::MINVAL), None)
+                }}
+
+                /// The largest possible value, for use with [between](struct.Client.html#method.between)
+                ///
+                /// __Example__: Get all users who are 18 or older.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("users").between(args!(18, r.maxval(), {{index: "age"}}));
+                /// # }}
+                /// ```
+
+                pub fn maxval(&self) -> Client {{
+                    util::make_cmd::<Client>(self, "maxval", Some(Type::MAXVAL), None)
+                }}
+
+                /// Get a nested field from a single object or a sequence of objects
+                ///
+                /// This is the same as chaining a [get_field](struct.Client.html#method.get_field) call for
+                /// each field in `path`. If any of the fields is missing the query raises a non-existence
+                /// error, which can be handled with [default](struct.Client.html#method.default).
+                ///
+                /// __Example__: Get the city of a user's address, or `"unknown"` if the user doesn't have
+                /// an address or the address has no city.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("users").get("alice").get_path(&["address", "location", "city"]).default("unknown");
+                /// # }}
+                /// ```
+
+                pub fn get_path(&self, path: &[&str]) -> Client {{
+                    util::get_path(self, path)
+                }}
+
+                /// Select `n` documents from a table the same way every time
+                ///
+                /// Unlike [sample](struct.Client.html#method.sample), which picks different documents on
+                /// every run, the documents are ordered by a UUID derived from `seed` and their `id`, so
+                /// the same seed always selects the same documents as long as the table doesn't change.
+                /// This is meant for tests. The whole table is sorted in memory, so it's only suitable
+                /// for tables small enough to be ordered without an index.
+                ///
+                /// __Example__: Select the same three marvel heroes on every run.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("marvel").sample_seeded(3, "test-seed");
+                /// # }}
+                /// ```
+
+                pub fn sample_seeded(&self, n: u64, seed: &str) -> Client {{
+                    util::sample_seeded(self, n, seed)
+                }}
+
+                /// Return a UUID derived from a name
+                ///
+                /// This is [uuid](struct.Client.html#method.uuid) given a string. The UUID is the same
+                /// every time for the same name, so it can be used as the primary key of a document
+                /// derived from a natural key. Unlike `r.uuid()` without a name, it can be used in an
+                /// `update` or a `replace` without setting `non_atomic`.
+                ///
+                /// __Example__: Get the user whose primary key was derived from their email address.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// r.table("users").get(r.uuid_of("slava@example.com"));
+                /// # }}
+                /// ```
+
+                pub fn uuid_of<T: IntoArg>(&self, name: T) -> Client {{
+                    util::make_cmd(self, "uuid", Some(Type::UUID), Some(name))
+                }}
+
+                /// Check whether a table has a document with the given value for an index
+                ///
+                /// This is `get_all(value, {{index}}).is_empty()` negated, which reads the index up to the
+                /// first matching document only, so it stays cheap however many documents match.
+                ///
+                /// __Example__: Check whether a user signed up with an email address.
+                ///
+                /// ```reql
+                /// let taken = r.table("users").exists(conn, "email", "bob@example.com")?;
+                /// ```
+
+                pub fn exists<A: IntoArg, V: IntoArg>(&self, args: A, index: &str, value: V) -> Result<bool> {{
+                    io::exists(self, args, index, value)
+                }}
+
+                /// List the names of the databases, sorted
+                ///
+                /// Runs [db_list](struct.Client.html#method.db_list) and collects the names.
+                ///
+                /// __Example__: List the databases.
+                ///
+                /// ```reql
+                /// let dbs = r.databases(conn)?;
+                /// ```
+
+                pub fn databases<T: IntoArg>(&self, args: T) -> Result<Vec<String>> {{
+                    io::databases(self, args)
+                }}
+
+                /// List the names of the tables in a database, sorted
+                ///
+                /// Runs [table_list](struct.Client.html#method.table_list) and collects the names. The
+                /// default database is used unless it's called on a `db`.
+                ///
+                /// __Example__: List the tables of the `blog` database.
+                ///
+                /// ```reql
+                /// let tables = r.db("blog").tables(conn)?;
+                /// ```
+
+                pub fn tables<T: IntoArg>(&self, args: T) -> Result<Vec<String>> {{
+                    io::tables(self, args)
+                }}
+
+                /// Create a database unless it already exists
+                ///
+                /// Unlike [db_create](struct.Client.html#method.db_create), it doesn't fail if the database
+                /// is already there, which makes migrations safe to run more than once. Returns whether
+                /// the database was created.
+                ///
+                /// __Example__: Make sure the `blog` database exists.
+                ///
+                /// ```reql
+                /// let created = r.db_create_if_not_exists(conn, "blog")?;
+                /// ```
+
+                pub fn db_create_if_not_exists<T: IntoArg>(&self, args: T, name: &str) -> Result<bool> {{
+                    io::db_create_if_not_exists(self, args, name)
+                }}
+
+                /// Create a table unless it already exists
+                ///
+                /// Like [db_create_if_not_exists](struct.Client.html#method.db_create_if_not_exists) but
+                /// for [table_create](struct.Client.html#method.table_create).
+                ///
+                /// __Example__: Make sure the `posts` table exists in the `blog` database.
+                ///
+                /// ```reql
+                /// let created = r.db("blog").table_create_if_not_exists(conn, "posts")?;
+                /// ```
+
+                pub fn table_create_if_not_exists<T: IntoArg>(&self, args: T, name: &str) -> Result<bool> {{
+                    io::table_create_if_not_exists(self, args, name)
+                }}
+
+                /// Insert a document or update it if it already exists
+                ///
+                /// A shorthand for `insert` with `conflict: "update"`, so inserting a document whose
+                /// primary key is already in the table merges it into the existing one instead of
+                /// failing. This makes ingesting the same data more than once safe. The summary can be
+                /// deserialized into `reql_types::WriteResult<T>`, where `replaced` counts the updates.
+                ///
+                /// __Example__: Save a user whether it's new or not.
+                ///
+                /// ```reql
+                /// let status = r.table("users")
+                ///     .upsert(args!({{id: "bob", email: "bob@example.com"}}))
+                ///     .run::<WriteResult<User>>(conn)?;
+                /// ```
+
+                pub fn upsert<T: IntoArg>(&self, doc: T) -> Client {{
+                    util::upsert(self, doc)
+                }}
+
+                /// Insert a number of documents or update the ones that already exist
+                ///
+                /// Like [upsert](struct.Client.html#method.upsert) but for many documents at once.
+
+                pub fn upsert_many<T: IntoArg>(&self, docs: Vec<T>) -> Client {{
+                    util::upsert(self, docs)
+                }}
+
+                /// Construct a ReQL datum from any value serde can serialize
+                ///
+                /// The same as [expr](struct.Client.html#method.expr) given the value wrapped in `Json`.
+                /// If the value fails to serialize, running the query returns the error.
+                ///
+                /// __Example__: Insert a post defined as a struct.
+                ///
+                /// ```reql
+                /// r.table("posts").insert(r.expr_serialize(&post)).run::<WriteStatus>(conn)?;
+                /// ```
+
+                pub fn expr_serialize<T: Serialize>(&self, value: T) -> Client {{
+                    util::expr_serialize(self, value)
+                }}
+
+                /// Splice an array of arguments into another term
+                ///
+                /// `r.args` is a special term that's used to splice an array of arguments into another
+                /// term. Unlike the [args](macro.args.html) macro, the array doesn't need to be known at
+                /// compile time so it's useful when you want to call a variadic term such as
+                /// [get_all](struct.Client.html#method.get_all) with a set of arguments produced at runtime.
+                ///
+                /// __Example__: Get all the people whose names are in `names`.
+                ///
+                /// ```rust
+                /// # #![allow(unused_must_use)]
+                /// # #[macro_use] extern crate reql;
+                /// # fn main() {{
+                /// # use reql::Client;
+                /// # let r = Client::new();
+                /// let names = vec!["Alice", "Bob"];
+                /// r.table("people").get_all(r.args(names));
+                /// # }}
+                /// ```
+                ///
+                /// __Note:__ When using `r.args` with a command that takes optional arguments, you must not
+                /// include the optional arguments inside the array.
+
+                pub fn args<T: IntoArg>(&self, args: T) -> Client {{
+                    util::make_cmd(self, "args", Some(Type::ARGS), Some(args))
+                }}
+
+                /// Insert documents into a table in batches
+                ///
+                /// Inserting a very large number of documents in a single `insert` can run into the
+                /// server's query size limits. `insert_batched` splits `docs` into `insert` queries of at
+                /// most `batch_size` documents each and runs them one after the other on a single session.
+                /// It returns a single summary of all the batches and stops at the first batch that reports
+                /// an error, so the summary tells you how many documents made it in. A batch that can't be
+                /// run at all, like when the connection fails, is returned as an error instead.
+                ///
+                /// Running the batches with `durability: "soft"` makes them faster, but the server may
+                /// acknowledge them before writing them to disk. Pass `sync_on_complete: true` as well to
+                /// call [sync](struct.Client.html#method.sync) on the table once the batches are done, if
+                /// any document was written. A failed `sync` doesn't undo the inserts so it's reported in
+                /// the status, along with the writes.
+                ///
+                /// __Example__: Insert 5000 posts, 500 at a time.
+                ///
+                /// ```reql
+                /// let status = r.table("posts").insert_batched(conn, posts, 500)?;
+                /// ```
+                ///
+                /// __Example__: Insert them with soft durability and flush them at the end.
+                ///
+                /// ```reql
+                /// let opts = args!(conn, {{durability: "soft", sync_on_complete: true}});
+                /// let status = r.table("posts").insert_batched(opts, posts, 500)?;
+                /// ```
+
+                pub fn insert_batched<A, I, D>(&self, args: A, docs: I, batch_size: usize) -> Result<BatchStatus>
+                    where A: IntoArg, I: IntoIterator<Item = D>, D: Serialize
+                {{
+                    io::insert_batched(self, args, docs, batch_size)
+                }}
+
+                /// Run a number of independent queries over a single session
+                ///
+                /// Running queries one by one opens a new session, or checks one out of the pool, for each
+                /// of them. `run_many` writes all the `queries` to a single session instead, letting the
+                /// server work on them at the same time, and returns their results in the same order as the
+                /// queries. Each result is either a single value or, for queries returning a sequence, the
+                /// whole sequence so `T` should be a `Vec` in that case. Changefeeds are not supported since
+                /// they never finish. `args` takes the same options as `run`, which apply to every query.
+                ///
+                /// __Example__: Get a number of posts by their ids.
+                ///
+                /// ```reql
+                /// let queries = ids.iter().map(|id| r.table("posts").get(id.as_str())).collect();
+                /// let posts = r.run_many::<_, Post>(conn, queries)?;
+                /// ```
+
+                pub fn run_many<A, T>(&self, args: A, queries: Vec<Client>) -> Result<Vec<Result<T>>>
+                    where A: IntoArg, T: DeserializeOwned
+                {{
+                    io::run_many(self, args, queries)
+                }}
+
+                /// Run a query that has already been built as a `Term`
+                ///
+                /// This skips the command builder entirely and submits `term` as it is, which is useful
+                /// for queries built programmatically or by other tools. Everything else, including the
+                /// connection options and the options passed in `args`, works just like
+                /// [run](trait.Run.html#tymethod.run).
+                ///
+                /// __Example__: Run `r.expr(1)` built by hand.
+                ///
+                /// ```reql
+                /// let mut datum = Datum::new();
+                /// datum.set_field_type(DT::R_NUM);
+                /// datum.set_r_num(1.0);
+                /// let mut term = Term::new();
+                /// term.set_field_type(TT::DATUM);
+                /// term.set_datum(datum);
+                /// let one = r.run_term::<_, i32>(conn, term)?;
+                /// ```
+
+                pub fn run_term<A, T>(&self, args: A, term: Term) -> Result<Response<T>>
+                    where A: IntoArg, T: DeserializeOwned + Send + 'static
+                {{
+                    io::run_term(self, args, term)
+                }}
+
+                /// Run a command that fails if the server doesn't respond in time
+                ///
+                /// This takes the same arguments as `run`. If the first response doesn't arrive within
+                /// `timeout`, the query is stopped on the server and the response returns a
+                /// `DriverError::Timeout`.
+                ///
+                /// __Example__: Give up on a slow query after a second.
+                ///
+                /// ```reql
+                /// let users = r.table("users").run_timeout::<_, User>(conn, Duration::from_secs(1))?;
+                /// ```
+
+                pub fn run_timeout<A, T>(&self, args: A, timeout: Duration) -> Result<Response<T>>
+                    where A: IntoArg, T: DeserializeOwned + Send + 'static
+                {{
+                    io::run_timeout(self, args, timeout)
+                }}
+
+                /// Run a changefeed that restarts itself when the connection fails
+                ///
+                /// This takes the same arguments as `run`. Whenever the feed fails because of the
+                /// connection, it's submitted again and a `FeedItem::Gap` is returned to mark the point
+                /// where changes may have been missed. Every other item is a `FeedItem::Doc` holding
+                /// what `run` would have returned.
+                ///
+                /// __Example__: Follow the changes to a table across connection failures.
+                ///
+                /// ```reql
+                /// let feed = r.table("posts").changes().run_resilient::<_, Change<Post, Post>>(conn)?;
+                /// for item in feed.wait() {{
+                ///     match item? {{
+                ///         FeedItem::Doc(change) => println!("{{:?}}", change),
+                ///         FeedItem::Gap => println!("some changes may have been missed"),
+                ///     }}
+                /// }}
+                /// ```
+
+                pub fn run_resilient<A, T>(&self, args: A) -> Result<ResilientFeed<T>>
+                    where A: IntoArg, T: DeserializeOwned + Send + 'static
+                {{
+                    io::run_resilient(self, args)
+                }}
+
+                /// Keep the top documents of an ordered query up to date
+                ///
+                /// Opens a changefeed on an `order_by(...).limit(n)` query, including the offsets of the
+                /// documents, and applies its changes to a local copy of the window. Each item of the
+                /// returned stream is the whole window in order, starting with the initial one once the
+                /// feed is ready. The feed is restarted if the connection fails, like with `run_resilient`.
+                ///
+                /// __Example__: Follow the top 10 players of a leaderboard.
+                ///
+                /// ```reql
+                /// let feed = r.table("players")
+                ///     .order_by(args!({{index: r.desc("score")}}))
+                ///     .limit(10)
+                ///     .top_n_feed::<_, Player>(conn)?;
+                /// for top in feed.wait() {{
+                ///     println!("{{:?}}", top?);
+                /// }}
+                /// ```
+
+                pub fn top_n_feed<A, T>(&self, args: A) -> Result<TopN<T>>
+                    where A: IntoArg, T: DeserializeOwned + Send + Clone + 'static
+                {{
+                    io::top_n_feed(self, args)
+                }}
+
                 {}
             }}
         "#, header, commands);
@@ -238,7 +809,9 @@ impl Command
     fn gen_docs(&self, mut docs: String) -> (bool, String)
     {
         let mut no_args = false;
+        let mut takes_args = false;
         let cmd = format!("{}()", self.menu.name);
+        let call = format!(".{}(", self.menu.name);
 
         let mut doc_block = false;
         let mut parse = false;
@@ -254,6 +827,12 @@ impl Command
                 if line.contains(&cmd) {
                     no_args = true;
                 }
+                // Some of those commands also take optional arguments
+                if let Some(i) = line.find(&call) {
+                    if !line[i + call.len()..].starts_with(')') {
+                        takes_args = true;
+                    }
+                }
                 self.fixup(line)
             })
             .filter(|line| {
@@ -309,6 +888,44 @@ impl Command
             doc_str.push_str(&docs);
         }
 
+        if no_args && takes_args {
+            let name = self.menu.method.as_ref().unwrap_or(&self.menu.permalink);
+            match name.as_str() {
+                // The arguments of these are easy to miss since the Rust methods don't take any
+                "round" | "ceil" | "floor" | "random" => {
+                    doc_str.push_str("///\n");
+                    doc_str.push_str(&format!("/// __Note:__ `{0}` doesn't take arguments directly in Rust. Use \
+                                               [with_args](struct.Client.html#method.with_args)\n\
+                                               /// to pass them, for example `.{0}().with_args(args!(...))`.\n",
+                                              name));
+                }
+                "min" | "max" => {
+                    doc_str.push_str("///\n");
+                    doc_str.push_str(&format!("/// __Note:__ Without an index `{0}` has to scan the whole sequence. \
+                                               To use an index instead write\n\
+                                               /// `.{0}().with_args(args!({{index: \"points\"}}))`.\n",
+                                              name));
+                }
+                "count" => {
+                    doc_str.push_str("///\n");
+                    doc_str.push_str("/// __Note:__ Counting a table with an index, like \
+                                      `.count().with_args(args!({index: \"id\"}))`, reads the index\n\
+                                      /// instead of every document, which is cheaper for large tables. The \
+                                      driver sends it as\n\
+                                      /// `between(r.minval, r.maxval, {index: \"id\"}).count()` so \
+                                      documents without a value for\n\
+                                      /// the index are not counted. A `count` with a predicate has to \
+                                      scan the whole sequence.\n");
+                }
+                _ => {}
+            }
+        }
+
+        for note in notes(&self.menu.permalink) {
+            doc_str.push_str("///\n");
+            doc_str.push_str(&note);
+        }
+
         (no_args, doc_str)
     }
 
@@ -321,3 +938,173 @@ impl Command
             .collect()
     }
 }
+
+// Notes on using a command in Rust, looked up by its permalink
+fn notes(permalink: &str) -> Vec<String>
+{
+    let write_result = "/// __Note:__ In Rust the summary can be deserialized into `reql_types::WriteResult<T>`. \
+                        With\n\
+                        /// `return_changes: true` its `changes` hold the old and new values as `T`, for \
+                        example\n\
+                        /// `.run::<WriteResult<Post>>(conn)`.\n";
+    match permalink {
+        "eq" | "ne" | "gt" | "ge" | "lt" | "le" => {
+            vec![format!("/// __Note:__ In Rust more than two values are passed using `args!`, for \
+                          example\n\
+                          /// `r.{0}(args!(a, b, c))` or `a.{0}(args!(b, c))`. At least two values are \
+                          required.\n",
+                         permalink)]
+        }
+        "insert" => vec![String::from(write_result)],
+        "literal" => {
+            vec![String::from("/// __Note:__ In Rust a field is removed with \
+                               `r.table(\"users\").get(1).update(args!({temp_field: r.literal()}))`.\n\
+                               /// The field is removed from the document rather than set to `null`.\n")]
+        }
+        "sample" => {
+            vec![String::from("/// __Note:__ The documents are picked on the server, so they are different on every run \
+                               even\n\
+                               /// when the connection is `reproducible`. Use \
+                               [sample_seeded](struct.Client.html#method.sample_seeded) when you need\n\
+                               /// the same documents every time, like in tests.\n")]
+        }
+        "filter" => {
+            vec![String::from("/// __Note:__ In Rust an object predicate is written as \
+                               `filter(args!({status: \"active\", tier: \"gold\"}))`.\n\
+                               /// Nested objects, like `args!({address: {city: \"Paris\"}})`, match nested \
+                               fields.\n")]
+        }
+        "changes" => {
+            vec![String::from("/// __Note:__ In Rust changes can be deserialized into `reql_types::Change<O, N>`. This includes \
+                               point\n\
+                               /// changefeeds on `get` and `get_all`, such as\n\
+                               /// `r.table(\"users\").get_all(args!(\"u1\", \"u2\")).changes().with_args(args!({include_initial: \
+                               true}))`,\n\
+                               /// where `old_val` is `None` for inserted documents and `new_val` is `None` for \
+                               deleted ones.\n")]
+        }
+        "now" => {
+            vec![String::from("/// __Note:__ The driver always sends `now` to the server as it is and never replaces it \
+                               with a\n\
+                               /// time of its own, so the guarantee above holds. Since its value changes from one run \
+                               to the\n\
+                               /// next, queries using it are rejected when the connection is `reproducible`.\n")]
+        }
+        "range" => {
+            vec![String::from("/// __Note:__ In Rust the three forms are `r.range()`, `r.range().with_args(args!(4))` and\n\
+                               /// `r.range().with_args(args!(4, 8))`. Since the first one never ends, `run` rejects \
+                               queries\n\
+                               /// that don't bound it, for example with `r.range().limit(4)`.\n")]
+        }
+        "merge" => {
+            vec![String::from("/// __Note:__ In Rust a function is passed through `args!`. It can return an object that runs a\n\
+                               /// subquery for each row, for example\n\
+                               /// `r.table(\"posts\").merge(args!(|post| args!({author: \
+                               r.table(\"users\").get(post.get_field(\"author_id\"))})))`.\n")]
+        }
+        "do" => {
+            vec![String::from("/// __Note:__ In Rust the function is passed last, after the values it is called with, for\n\
+                               /// example `r.do_(args!(r.table(\"users\").get(1), |user| user.get_field(\"name\")))`.\n")]
+        }
+        "between" => {
+            vec![String::from("/// __Note:__ In Rust the bounds of a compound index are passed as arrays, for example\n\
+                               /// `between(args!([cat, start], [cat, end], {index: \"cat_ts\"}))`. Arrays are \
+                               compared element by\n\
+                               /// element, so fixing the first element ranges over the second one within that \
+                               category only.\n")]
+        }
+        "http" => {
+            vec![String::from("/// __Note:__ In Rust a `page` function is passed as a closure, for example\n\
+                               /// `r.http(args!(url, {page: |info| info.get_field(\"body\").get_field(\"next\"), \
+                               page_limit: 5}))`.\n\
+                               /// The results of every page are streamed as a single sequence. The driver checks \
+                               that `page` and\n\
+                               /// `page_limit` are given together before sending the query.\n")]
+        }
+        "with_fields" => {
+            vec![String::from("/// __Note:__ Documents missing any of the fields are dropped entirely, not returned \
+                               with the\n\
+                               /// fields they do have. In Rust nested fields are passed as an object, for example\n\
+                               /// `with_fields(args!(\"id\", \"user\", {contact: {phone: \"work\"}}))`.\n")]
+        }
+        "expr" => {
+            vec![String::from("/// __Note:__ In Rust vectors, `HashMap`s with `String` keys and `Option`s are converted \
+                               directly,\n\
+                               /// with `None` sent as `null`. Wrap any other type serde can serialize in `Json`, \
+                               for example\n\
+                               /// `r.expr(Json(post))`, or pass it to \
+                               [expr_serialize](struct.Client.html#method.expr_serialize).\n")]
+        }
+        "during" => {
+            vec![String::from("/// __Note:__ In Rust the bounds can be times built with commands like `r.iso8601` or a\n\
+                               /// `reql_types::DateTime`, which is sent as a `TIME`, for example\n\
+                               /// `row.get_field(\"ts\").during(args!(start, end, {left_bound: \"closed\", \
+                               right_bound: \"open\"}))`.\n")]
+        }
+        "reduce" => {
+            vec![String::from("/// __Note:__ In Rust a `default` can be passed to return for empty sequences instead of \
+                               an error,\n\
+                               /// for example `r.table(\"posts\").map(args!(|doc| doc.get_field(\"views\")))\
+                               .reduce(args!(|a, b| a.add(b), {default: 0}))`.\n")]
+        }
+        "update" | "replace" => {
+            vec![String::from(write_result),
+                 format!("/// __Note:__ Queries using `r.random()`, `r.js()`, `r.http()` or `r.uuid()` \
+                          without a name are\n\
+                          /// rejected before they are sent unless `non_atomic` is set, for example\n\
+                          /// `r.table(\"posts\").get(1).{}(args!(|post| ..., {{non_atomic: true}}))`.\n",
+                         permalink)]
+        }
+        "uuid" => {
+            vec![String::from("/// __Note:__ A UUID based on a string is the same every time, so it can be used as the primary key \
+                               of a\n\
+                               /// document derived from a natural key, for example\n\
+                               /// `r.table(\"users\").get(r.uuid_of(\"slava@example.com\"))`.\n")]
+        }
+        "info" => {
+            vec![String::from("/// __Note:__ In Rust the information about a database or a table can be deserialized \
+                               into\n\
+                               /// `reql_types::ObjectInfo`, for example `r.table(\"marvel\").info().run::<ObjectInfo>(conn)`.\n")]
+        }
+        "coerce_to" => {
+            vec![String::from("/// __Note:__ A value that can't be coerced, like `r.expr(\"abc\").coerce_to(\"number\")`, \
+                               makes the\n\
+                               /// query fail with an `Error::Runtime` holding a `RuntimeError::QueryLogic`, which \
+                               can be matched\n\
+                               /// to handle bad input.\n")]
+        }
+        "delete" => {
+            vec![String::from(write_result),
+                 String::from("/// __Note:__ Deleting a range of an index only reads the documents in that range, for \
+                               example\n\
+                               /// `r.table(\"events\").between(args!(r.minval(), cutoff, {index: \"ts\"})).delete()`. \
+                               Such a bounded\n\
+                               /// selection isn't treated as a table-wide write, so it runs on connections in \
+                               `safe_mode` too.\n")]
+        }
+        "db" => {
+            vec![String::from("/// __Note:__ The database name is checked when the command is built. Keep the returned \
+                               `Client`\n\
+                               /// around to reach several tables of the same database, like `let heroes = \
+                               r.db(\"heroes\");`\n\
+                               /// followed by `heroes.table(\"marvel\")` and `heroes.table(\"dc\")`.\n")]
+        }
+        "get_nearest" => {
+            vec![String::from("/// __Note:__ In Rust the optional arguments use the names the server expects, for example\n\
+                               /// `get_nearest(args!(base, {index: \"location\", max_results: 25, max_dist: 10, unit: \
+                               \"km\"}))`.\n\
+                               /// The results can be deserialized into `Vec<reql_types::Nearest<T>>`.\n")]
+        }
+        "union" => {
+            vec![String::from("/// __Note:__ In Rust `interleave` is passed alongside the other sequences, for example\n\
+                               /// `r.table(\"marvel\").order_by(args!({index: \"name\"})).union(args!(dc, {interleave: \
+                               \"name\"}))`.\n")]
+        }
+        "js" => {
+            vec![String::from("/// __Note:__ The code is evaluated on the server, so never build it from untrusted input. \
+                               In\n\
+                               /// Rust the timeout is passed as `r.js(args!(\"while(true) {}\", {timeout: 1.3}))`.\n")]
+        }
+        _ => Vec::new(),
+    }
+}
@@ -84,6 +84,10 @@ impl<'a> Args<'a> {
         let mut key = String::new();
         let mut val = Vec::new();
         let last = tt.len()-1;
+        let mut typ = Type::Expr;
+        let mut found_lbar = false;
+        let mut found_rbar = false;
+        let mut found_body = false;
 
         for (i, tree) in tt.into_iter().enumerate() {
             if let TokenTree::Token(Token::Colon) = tree {
@@ -106,8 +110,10 @@ impl<'a> Args<'a> {
             }
             let token_is_comma = if let TokenTree::Token(Token::Comma) = tree { true } else { false };
             let on_last_token = if i == last { true } else { false };
-            let val_end = token_is_comma || on_last_token;
-            if !token_is_comma {
+            // Commas separating the parameters of a closure don't end the value
+            let in_closure_params = Type::new(&val, &tree, &mut typ, &mut found_lbar, &mut found_rbar, &mut found_body) == Type::Closure && !found_body;
+            let val_end = (token_is_comma && !in_closure_params) || on_last_token;
+            if !token_is_comma || in_closure_params {
                 val.push(tree);
             }
             if val_end {
@@ -122,6 +128,10 @@ impl<'a> Args<'a> {
                 }
                 is_key = true;
                 val = Vec::new();
+                typ = Type::Expr;
+                found_lbar = false;
+                found_rbar = false;
+                found_body = false;
             }
         }
 
@@ -203,7 +213,7 @@ impl Group {
                 let mut args = Tokens::new();
                 let mut found_lbar = false;
                 let mut found_rbar = false;
-                for (i, token) in tt.into_iter().enumerate() {
+                for token in tt {
                     token.to_tokens(&mut func);
                     if found_rbar {
                         continue;
@@ -225,7 +235,7 @@ impl Group {
                     if found_lbar {
                         quote!(: Client,)
                             .to_tokens(&mut func);
-                        quote!(var!(#i),)
+                        quote!(var!(),)
                             .to_tokens(&mut args);
                     }
                 }
@@ -236,14 +246,19 @@ impl Group {
                 closure.to_tokens(&mut tokens);
             }
             Group::List(tt) => {
-                let mut list = quote!(let mut list_arg = Arg::new(););
+                let mut list = quote!(let mut list_arg = Arg::new_array(););
                 for group in tt {
-                    quote!(let mut list_val = Arg::new();)
-                        .to_tokens(&mut list);
-                    group.tokenise("list_val", false)
-                        .to_tokens(&mut list);
-                    quote!(list_arg.add_arg(list_val);)
-                        .to_tokens(&mut list);
+                    // Build each value in its own scope so nested lists and
+                    // objects don't shadow the variables used here
+                    let val = group.tokenise("list_val", false);
+                    quote!(
+                        let list_val = {
+                            let mut list_val = Arg::new();
+                            #val
+                            list_val
+                        };
+                        list_arg.add_arg(list_val);
+                    ).to_tokens(&mut list);
                 }
                 quote!(#var.add_arg(list_arg);)
                         .to_tokens(&mut list);
@@ -256,10 +271,16 @@ impl Group {
                         .to_tokens(&mut obj);
                 }
                 for (key, group) in tt {
-                    quote!(let mut obj_val = Arg::new();)
-                        .to_tokens(&mut obj);
-                    group.tokenise("obj_val", false)
-                        .to_tokens(&mut obj);
+                    // Build each value in its own scope so nested lists and
+                    // objects don't shadow the variables used here
+                    let val = group.tokenise("obj_val", false);
+                    quote!(
+                        let obj_val = {
+                            let mut obj_val = Arg::new();
+                            #val
+                            obj_val
+                        };
+                    ).to_tokens(&mut obj);
                     if last {
                         quote!(
                             match Arg::create_term_pair(#key, obj_val) {
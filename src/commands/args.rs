@@ -1,6 +1,11 @@
-use {Arg, Client, Connection, IntoArg, Result};
-use ql2::proto::{Term, Term_AssocPair as TermPair};
+use {Arg, Client, Connection, ConnectionListener, IntoArg, Json, Metrics, Result, SharedConnection};
+use ql2::proto::{Term, Term_AssocPair as TermPair, Term_TermType as TermType};
+use reql_types::DateTime;
+use serde::Serialize;
+use serde_json::to_value;
 use serde_json::value::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
 use tokio_core::reactor::{Handle, Remote};
 use types::FromJson;
 
@@ -21,6 +26,8 @@ impl IntoArg for Client
             term: self.term,
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -34,6 +41,8 @@ impl IntoArg for Term
             term: Ok(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -47,6 +56,8 @@ impl IntoArg for String
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -60,6 +71,8 @@ impl IntoArg for char
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -73,6 +86,8 @@ impl<'a> IntoArg for &'a String
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -86,6 +101,8 @@ impl<'a> IntoArg for &'a str
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -99,6 +116,8 @@ impl IntoArg for f32
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -112,6 +131,8 @@ impl IntoArg for i32
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -125,6 +146,8 @@ impl IntoArg for u32
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -138,6 +161,8 @@ impl IntoArg for f64
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -151,6 +176,8 @@ impl IntoArg for i64
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -164,6 +191,8 @@ impl IntoArg for u64
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -177,6 +206,8 @@ impl IntoArg for bool
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -190,10 +221,74 @@ impl IntoArg for Value
             term: Term::from_json(self),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
 
+impl<T: IntoArg> IntoArg for Vec<T>
+{
+    fn into_arg(self) -> Arg
+    {
+        let mut arg = Arg::new_array();
+        let mut strings = Vec::new();
+        for val in self {
+            let val = val.into_arg();
+            strings.push(val.string.clone());
+            arg.add_arg(val);
+        }
+        arg.string = format!("[{}]", strings.join(", "));
+        arg
+    }
+}
+
+impl<T: IntoArg> IntoArg for Option<T>
+{
+    fn into_arg(self) -> Arg
+    {
+        match self {
+            Some(val) => val.into_arg(),
+            None => Value::Null.into_arg(),
+        }
+    }
+}
+
+impl<V: Serialize> IntoArg for HashMap<String, V>
+{
+    fn into_arg(self) -> Arg
+    {
+        Json(self).into_arg()
+    }
+}
+
+impl IntoArg for DateTime
+{
+    fn into_arg(self) -> Arg
+    {
+        Json(self).into_arg()
+    }
+}
+
+impl<T: Serialize> IntoArg for Json<T>
+{
+    fn into_arg(self) -> Arg
+    {
+        // Serialize only once so the string and the term can't disagree
+        let (string, term) = match to_value(self.0) {
+            Ok(value) => (value.to_string(), Term::from_json(value)),
+            Err(error) => (String::new(), Err(error.into())),
+        };
+        Arg {
+            string: string,
+            term: term,
+            pool: None,
+            remote: None,
+            metrics: None,
+            listener: None,
+        }
+    }
+}
 
 impl IntoArg for Connection
 {
@@ -204,11 +299,21 @@ impl IntoArg for Connection
             term: Ok(Term::new()),
             pool: Some(self),
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 }
 
 
+impl<'a> IntoArg for &'a SharedConnection
+{
+    fn into_arg(self) -> Arg
+    {
+        self.connection().into_arg()
+    }
+}
+
 impl<'a> IntoArg for &'a Handle
 {
     fn into_arg(self) -> Arg
@@ -218,6 +323,8 @@ impl<'a> IntoArg for &'a Handle
             term: Ok(Term::new()),
             pool: None,
             remote: Some(self.remote().clone()),
+            metrics: None,
+            listener: None,
         }
     }
 }
@@ -232,6 +339,38 @@ impl IntoArg for Remote
             term: Ok(Term::new()),
             pool: None,
             remote: Some(self),
+            metrics: None,
+            listener: None,
+        }
+    }
+}
+
+impl<M: Metrics + 'static> IntoArg for Arc<M>
+{
+    fn into_arg(self) -> Arg
+    {
+        Arg {
+            string: String::from("metrics"),
+            term: Ok(Term::new()),
+            pool: None,
+            remote: None,
+            metrics: Some(self),
+            listener: None,
+        }
+    }
+}
+
+impl IntoArg for Arc<ConnectionListener>
+{
+    fn into_arg(self) -> Arg
+    {
+        Arg {
+            string: String::from("listener"),
+            term: Ok(Term::new()),
+            pool: None,
+            remote: None,
+            metrics: None,
+            listener: Some(self),
         }
     }
 }
@@ -256,9 +395,21 @@ impl Arg
             term: Ok(Term::new()),
             pool: None,
             remote: None,
+            metrics: None,
+            listener: None,
         }
     }
 
+    #[doc(hidden)]
+    pub fn new_array() -> Arg
+    {
+        let mut term = Term::new();
+        term.set_field_type(TermType::MAKE_ARRAY);
+        let mut arg = Arg::new();
+        arg.term = Ok(term);
+        arg
+    }
+
     #[doc(hidden)]
     pub fn set_string(&mut self, string: &str)
     {
@@ -271,6 +422,15 @@ impl Arg
         self.term = term;
     }
 
+    #[doc(hidden)]
+    pub fn term(&self) -> Result<&Term>
+    {
+        match self.term {
+            Ok(ref term) => Ok(term),
+            Err(ref error) => Err(error.clone()),
+        }
+    }
+
     #[doc(hidden)]
     pub fn add_arg(&mut self, arg: Arg)
     {
@@ -280,6 +440,12 @@ impl Arg
         if let Some(remote) = arg.remote {
             self.remote = Some(remote);
         }
+        if let Some(metrics) = arg.metrics {
+            self.metrics = Some(metrics);
+        }
+        if let Some(listener) = arg.listener {
+            self.listener = Some(listener);
+        }
         let mut error = None;
         if let Ok(ref mut term) = self.term {
             match arg.term {
@@ -313,3 +479,70 @@ impl Arg
         Ok(temp_pair)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::from_str;
+
+    #[derive(Serialize)]
+    struct Post
+    {
+        id: u64,
+        title: String,
+        tags: Vec<String>,
+        draft: Option<bool>,
+    }
+
+    fn json_term(json: &str) -> Term
+    {
+        Term::from_json(from_str::<Value>(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn json_sends_a_struct_as_a_datum()
+    {
+        let post = Post {
+            id: 1,
+            title: String::from("Hello"),
+            tags: vec![String::from("rust")],
+            draft: None,
+        };
+        let json = r#"{"id": 1, "title": "Hello", "tags": ["rust"], "draft": null}"#;
+        let arg = Json(post).into_arg();
+        assert_eq!(from_str::<Value>(&arg.string).unwrap(), from_str::<Value>(json).unwrap());
+        assert_eq!(arg.term.unwrap(), json_term(json));
+    }
+
+    #[test]
+    fn json_sends_a_map_as_a_datum()
+    {
+        let mut scores = HashMap::new();
+        scores.insert(String::from("alice"), 3);
+        scores.insert(String::from("bob"), 5);
+        let json = r#"{"alice": 3, "bob": 5}"#;
+        assert_eq!(scores.clone().into_arg().term.unwrap(), json_term(json));
+        let expr = Client::new().expr_serialize(&scores);
+        assert_eq!(expr.term.unwrap(), Client::new().expr(Json(scores)).term.unwrap());
+    }
+
+    #[test]
+    fn json_returns_serialization_errors()
+    {
+        // Only string keys can be serialized as an object
+        let mut map = HashMap::new();
+        map.insert((1, 2), "pair");
+        assert!(Json(&map).into_arg().term.is_err());
+        assert!(Client::new().expr_serialize(&map).term.is_err());
+    }
+
+    #[test]
+    fn date_times_are_sent_as_times()
+    {
+        let json = r#"{"$reql_type$": "TIME", "epoch_time": 1500000000.25, "timezone": "+00:00"}"#;
+        let time: DateTime = from_str(json).unwrap();
+        let arg = time.into_arg();
+        assert_eq!(from_str::<Value>(&arg.string).unwrap(), from_str::<Value>(json).unwrap());
+        assert_eq!(arg.term.unwrap(), json_term(json));
+    }
+}
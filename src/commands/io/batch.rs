@@ -0,0 +1,153 @@
+use super::{check_query, checkout, find_datum, prepare, take_bool};
+use super::many::run_on_session;
+use super::response::deserialize;
+use {Arg, Client, IntoArg, Prepared, Result, Session, SessionQueries};
+use errors::DriverError;
+use reql_types::{BatchStatus, WriteStatus};
+use serde::Serialize;
+use serde_json::value::{Value, to_value};
+use slog::Logger;
+use std::time::Instant;
+
+pub fn insert_batched<A, I, D>(client: &Client, args: A, docs: I, batch_size: usize) -> Result<BatchStatus>
+    where A: IntoArg,
+          I: IntoIterator<Item = D>,
+          D: Serialize
+{
+    if batch_size == 0 {
+        let msg = String::from("`insert_batched` requires a `batch_size` greater than 0");
+        return Err(DriverError::Other(msg))?;
+    }
+    let mut arg = args.into_arg();
+    let sync = take_sync_on_complete(&mut arg)?;
+    let logger = client.logger.new(o!("command" => "insert_batched"));
+    let prepared = prepare(client, "insert_batched", arg)?;
+
+    // All the batches are written to the same session, one after the other
+    let start = Instant::now();
+    let session = checkout(&prepared.pool, prepared.run_opts.checkout_timeout);
+    prepared.cfg.metrics.pool_checkout(start.elapsed());
+    let mut session = session?;
+    // So `drain` waits for the batches and can stop them
+    let registered = SessionQueries::new(prepared.cfg.queries.clone(), &session);
+    let res = insert_all(&mut session, &registered, &prepared, client, docs, batch_size, sync, &logger);
+    if res.is_err() {
+        // Part of a response may still be waiting to be read
+        session.broken = true;
+    }
+    res
+}
+
+fn insert_all<I, D>(session: &mut Session,
+                    registered: &SessionQueries,
+                    prepared: &Prepared,
+                    client: &Client,
+                    docs: I,
+                    batch_size: usize,
+                    sync: bool,
+                    logger: &Logger)
+                    -> Result<BatchStatus>
+    where I: IntoIterator<Item = D>,
+          D: Serialize
+{
+    let mut summary = WriteStatus::default();
+    let mut docs = docs.into_iter().peekable();
+    let mut batches = 0;
+    while docs.peek().is_some() {
+        let mut batch = Vec::with_capacity(batch_size);
+        for doc in docs.by_ref().take(batch_size) {
+            batch.push(to_value(doc)?);
+        }
+        batches += 1;
+        debug!(logger, "inserting batch number {} ({} documents)", batches, batch.len());
+        let res = run_cmd(session, registered, prepared, client.insert(Value::Array(batch)))?;
+        let status: WriteStatus = deserialize(res)?;
+        let failed = status.errors > 0;
+        merge(&mut summary, status);
+        if failed {
+            warn!(logger, "batch number {} failed, stopping", batches);
+            break;
+        }
+    }
+    let mut status = BatchStatus::default();
+    // Flush what made it in, even if a batch failed
+    if sync && summary.inserted + summary.replaced > 0 {
+        debug!(logger, "syncing the table");
+        match run_cmd(session, registered, prepared, client.sync()) {
+            Ok(res) => status.synced = res["synced"].as_u64() == Some(1),
+            Err(error) => {
+                warn!(logger, "syncing the table failed: {}", error);
+                session.broken = true;
+                status.sync_error = Some(error.to_string());
+            }
+        }
+    }
+    status.write = summary;
+    Ok(status)
+}
+
+// Run a command on the session, checking it just like `run` would
+fn run_cmd(session: &mut Session, registered: &SessionQueries, prepared: &Prepared, cmd: Client) -> Result<Value>
+{
+    let mut term = cmd.term?;
+    check_query(prepared, &mut term, &cmd.query)?;
+    run_on_session(session, registered, prepared, &term, &cmd.query)
+}
+
+// `sync_on_complete` is only meant for the driver so the inserts don't get it
+fn take_sync_on_complete(arg: &mut Arg) -> Result<bool>
+{
+    let mut sync = false;
+    if let Ok(ref mut term) = arg.term {
+        let optargs = term.take_optargs().into_vec();
+        for mut pair in optargs {
+            if pair.get_key() == "sync_on_complete" {
+                let key = pair.take_key();
+                sync = take_bool(&key, find_datum(pair.take_val()))?;
+            } else {
+                term.mut_optargs().push(pair);
+            }
+        }
+    }
+    Ok(sync)
+}
+
+fn merge(summary: &mut WriteStatus, status: WriteStatus)
+{
+    summary.inserted += status.inserted;
+    summary.replaced += status.replaced;
+    summary.unchanged += status.unchanged;
+    summary.skipped += status.skipped;
+    summary.deleted += status.deleted;
+    summary.errors += status.errors;
+    if summary.first_error.is_none() {
+        summary.first_error = status.first_error;
+    }
+    if let Some(keys) = status.generated_keys {
+        let mut all = summary.generated_keys.take().unwrap_or_default();
+        all.extend(keys);
+        summary.generated_keys = Some(all);
+    }
+    if let Some(warnings) = status.warnings {
+        let mut all = summary.warnings.take().unwrap_or_default();
+        all.extend(warnings);
+        summary.warnings = Some(all);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_on_complete_is_kept_from_the_inserts()
+    {
+        let mut arg = args!({durability: "soft", sync_on_complete: true});
+        assert!(take_sync_on_complete(&mut arg).unwrap());
+        let term = arg.term.unwrap();
+        let left: Vec<_> = term.get_optargs().iter().map(|pair| pair.get_key()).collect();
+        assert_eq!(left, vec!["durability"]);
+        assert!(!take_sync_on_complete(&mut args!({durability: "soft"})).unwrap());
+        assert!(take_sync_on_complete(&mut args!({sync_on_complete: "yes"})).is_err());
+    }
+}
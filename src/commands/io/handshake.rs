@@ -47,11 +47,12 @@ struct AuthConfirmation
 
 impl Session
 {
-    pub fn handshake(&mut self, opts: &Opts) -> Result<()>
+    /// Open the session, returning the version of the server
+    pub fn handshake(&mut self, opts: &Opts) -> Result<String>
     {
         // Send desired version to the server
         let _ = self.stream.write_u32::<LittleEndian>(Version::V1_0 as u32)?;
-        parse_server_version(&self.stream)?;
+        let version = parse_server_version(&self.stream)?;
 
         // Send client first message
         let scram = ScramClient::new(&opts.user, &opts.password, None)?;
@@ -96,7 +97,7 @@ impl Session
                 parse_server_final(scram, &self.stream)?;
                 let _ = self.stream.flush()?;
 
-                Ok(())
+                Ok(version)
             }
             None => {
                 Err(io_error(String::from("Server did not send authentication \
@@ -107,10 +108,10 @@ impl Session
 
     pub fn is_valid(&mut self) -> Result<()>
     {
-        self.id = self.id.wrapping_add(1);
+        let token = self.next_token();
         let query = wrap_query(QueryType::START, Some(String::from("1")), None);
-        write_query(self, &query)?;
-        let resp = read_query(self)?;
+        write_query(self, token, &query)?;
+        let resp = read_query(self, token)?;
         let resp: ReqlResponse = from_slice(&resp[..])?;
         if let Some(respt) = ResponseType::from_i32(resp.t) {
             if let ResponseType::SUCCESS_ATOM = respt {
@@ -125,14 +126,14 @@ impl Session
     }
 }
 
-fn parse_server_version(stream: &TcpStream) -> Result<()>
+fn parse_server_version(stream: &TcpStream) -> Result<String>
 {
     let resp = parse_server_response(stream)?;
     let info: ServerInfo = from_str(&resp)?;
     if !info.success {
         return Err(io_error(resp.to_string()))?;
     };
-    Ok(())
+    Ok(info.server_version)
 }
 
 fn parse_server_response(stream: &TcpStream) -> Result<String>
@@ -0,0 +1,150 @@
+use super::{CONFIG, find_datum};
+use {Client, Connection, Document, Result, Run};
+use errors::DriverError;
+use futures::Stream;
+use ql2::proto::{Term, Term_TermType as TermType};
+use reql_types::ObjectInfo;
+
+/// Make sure the indexes used by a query exist on their tables
+pub fn validate_indexes(conn: Connection, db: &str, term: &Term) -> Result<()>
+{
+    match term.get_field_type() {
+        TermType::BETWEEN |
+        TermType::GET_ALL |
+        TermType::ORDER_BY |
+        TermType::MIN |
+        TermType::MAX => {
+            if let Some(index) = index_name(term) {
+                if let Some(table) = find_table(term) {
+                    check_index(conn, db, table, &index)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    for arg in term.get_args() {
+        validate_indexes(conn, db, arg)?;
+    }
+    Ok(())
+}
+
+fn index_name(term: &Term) -> Option<String>
+{
+    for pair in term.get_optargs() {
+        if pair.get_key() == "index" {
+            for mut datum in find_datum(pair.get_val().clone()) {
+                return Some(datum.take_r_str());
+            }
+        }
+    }
+    None
+}
+
+// Indexed commands are chained directly on the table they operate on
+fn find_table(term: &Term) -> Option<&Term>
+{
+    match term.get_args().first() {
+        Some(arg) => {
+            if arg.get_field_type() == TermType::TABLE {
+                Some(arg)
+            } else {
+                find_table(arg)
+            }
+        }
+        None => None,
+    }
+}
+
+// Returns the database and the name of a table term
+fn table_name(db: &str, table: &Term) -> (String, String)
+{
+    let mut db = db.to_string();
+    let mut name = String::new();
+    for arg in table.get_args() {
+        if arg.get_field_type() == TermType::DB {
+            for mut datum in find_datum(arg.clone()) {
+                db = datum.take_r_str();
+            }
+        } else if arg.has_datum() {
+            name = arg.get_datum().get_r_str().to_string();
+        }
+    }
+    (db, name)
+}
+
+fn check_index(conn: Connection, db: &str, table: &Term, index: &str) -> Result<()>
+{
+    let (db, name) = table_name(db, table);
+    let key = format!("{}.{}", db, name);
+    let cached = match CONFIG.read().get(&conn) {
+        Some(config) => config.indexes.get(&key).cloned(),
+        None => None,
+    };
+    if let Some(indexes) = cached {
+        if indexes.iter().any(|i| i == index) {
+            return Ok(());
+        }
+    }
+    // The index may have been created since we last looked
+    let indexes = table_indexes(conn, table)?;
+    let found = indexes.iter().any(|i| i == index);
+    if let Some(config) = CONFIG.write().get_mut(&conn) {
+        config.indexes.insert(key, indexes.clone());
+    }
+    if found {
+        Ok(())
+    } else {
+        let msg = format!("no such index '{}' on table '{}', available indexes: {:?}",
+                          index,
+                          name,
+                          indexes);
+        Err(DriverError::Other(msg))?
+    }
+}
+
+// Fetches the indexes of a table, including its primary key
+fn table_indexes(conn: Connection, table: &Term) -> Result<Vec<String>>
+{
+    let mut query = Client::new();
+    query.set_term(Ok(table.clone()));
+    let resp = query.info().run::<ObjectInfo>(conn)?;
+    let info = match resp.wait().next() {
+        Some(Ok(Some(Document::Expected(info)))) => info,
+        Some(Err(error)) => {
+            return Err(error);
+        }
+        res => {
+            let msg = format!("unexpected response from server: {:?}", res);
+            return Err(DriverError::Other(msg))?;
+        }
+    };
+    let mut indexes = Vec::new();
+    if let Some(pkey) = info.primary_key {
+        indexes.push(pkey);
+    }
+    if let Some(list) = info.indexes {
+        indexes.extend(list);
+    }
+    Ok(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexed_commands_name_their_table_and_index()
+    {
+        let r = Client::new();
+        let latest = r.db("blog").table("posts").order_by(args!({index: r.desc("date")})).limit(3).term.unwrap();
+        let order_by = &latest.get_args()[0];
+        assert_eq!(index_name(order_by), Some(String::from("date")));
+        let table = find_table(order_by).unwrap();
+        assert_eq!(table_name("test", table), (String::from("blog"), String::from("posts")));
+        let users = r.table("users").get_all(args!("alice", {index: "name"})).term.unwrap();
+        assert_eq!(index_name(&users), Some(String::from("name")));
+        assert_eq!(table_name("test", find_table(&users).unwrap()),
+                   (String::from("test"), String::from("users")));
+        assert_eq!(index_name(&r.table("users").get_all("alice").term.unwrap()), None);
+    }
+}
@@ -0,0 +1,191 @@
+use super::{check_query, checkout, prepare, read_query, read_response, wrap_query};
+use super::request::response_type;
+use super::response::deserialize;
+use {Client, IntoArg, Prepared, ReqlResponse, Result, Session, SessionQueries};
+use ql2::proto::{Query_QueryType as QueryType, Response_ResponseType as ResponseType, Term};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, from_slice};
+use std::collections::HashMap;
+use std::time::Instant;
+use types::Encode;
+
+/// Run independent queries over a single session
+///
+/// All the queries are written to the session upfront, each with its own
+/// token, and their responses are matched back to them using those tokens
+/// as they arrive.
+pub fn run_many<A, T>(client: &Client, args: A, queries: Vec<Client>) -> Result<Vec<Result<T>>>
+    where A: IntoArg,
+          T: DeserializeOwned
+{
+    let logger = client.logger.new(o!("command" => "run_many"));
+    let prepared = prepare(client, "run_many", args.into_arg())?;
+    let opts = encode_opts(&prepared);
+
+    let start = Instant::now();
+    let session = checkout(&prepared.pool, prepared.run_opts.checkout_timeout);
+    prepared.cfg.metrics.pool_checkout(start.elapsed());
+    let mut session = session?;
+    // So `drain` waits for the queries and can stop them
+    let registered = SessionQueries::new(prepared.cfg.queries.clone(), &session);
+
+    let mut results: Vec<Option<Result<T>>> = queries.iter().map(|_| None).collect();
+    let mut terms = Vec::new();
+    for (i, query) in queries.iter().enumerate() {
+        let checked = query.term.clone().and_then(|mut term| {
+            check_query(&prepared, &mut term, &query.query)?;
+            Ok(term)
+        });
+        match checked {
+            Ok(term) => {
+                debug!(logger, "{}", query.query);
+                terms.push((i, term));
+            }
+            Err(error) => results[i] = Some(Err(error)),
+        }
+    }
+    if let Err(error) = exchange(&mut session, &registered, &prepared, &queries, terms, opts, &mut results) {
+        // Responses to the other queries may still be on their way
+        session.broken = true;
+        return Err(error);
+    }
+
+    Ok(results.into_iter().map(Option::unwrap).collect())
+}
+
+// Write the queries to the session and read their results back
+fn exchange<T>(session: &mut Session,
+               registered: &SessionQueries,
+               prepared: &Prepared,
+               queries: &[Client],
+               terms: Vec<(usize, Term)>,
+               opts: Option<String>,
+               results: &mut Vec<Option<Result<T>>>)
+               -> Result<()>
+    where T: DeserializeOwned
+{
+    // Maps the token of each query to its position
+    let mut tokens = HashMap::new();
+    let mut values: Vec<Vec<Value>> = queries.iter().map(|_| Vec::new()).collect();
+    let mut pending = 0;
+    let start = Instant::now();
+    for (i, term) in terms {
+        let token = session.next_token();
+        let qry = wrap_query(QueryType::START, Some(term.encode()), opts.clone());
+        registered.write(session, token, &qry)?;
+        tokens.insert(token, i);
+        prepared.cfg.metrics.query_submitted();
+        pending += 1;
+    }
+    while pending > 0 {
+        let (token, resp) = read_response(session)?;
+        let i = match tokens.get(&token) {
+            Some(&i) if results[i].is_none() => i,
+            _ => {
+                warn!(session.logger, "response with unknown token {}", token);
+                continue;
+            }
+        };
+        let result: ReqlResponse = from_slice(&resp[..])?;
+        let res = match response_type(&result, &queries[i].query) {
+            Ok(ResponseType::SUCCESS_PARTIAL) => {
+                if let Value::Array(data) = result.r {
+                    values[i].extend(data);
+                }
+                // Ask for the rest of the sequence
+                let qry = wrap_query(QueryType::CONTINUE, None, None);
+                registered.write(session, token, &qry)?;
+                continue;
+            }
+            Ok(respt) => {
+                let data = values[i].drain(..).collect();
+                deserialize(result_value(respt, result.r, data))
+            }
+            Err(error) => Err(error),
+        };
+        registered.finish(token);
+        prepared.cfg.metrics.query_completed(start.elapsed(), res.is_ok());
+        results[i] = Some(res);
+        pending -= 1;
+    }
+    Ok(())
+}
+
+/// Run a single query on a session that has been checked out already
+///
+/// Used by commands that run several queries one after the other on the
+/// same session. The session must be marked as broken if this fails, since
+/// part of the response may still be waiting to be read.
+pub fn run_on_session(session: &mut Session,
+                      registered: &SessionQueries,
+                      prepared: &Prepared,
+                      term: &Term,
+                      query: &str)
+                      -> Result<Value>
+{
+    let start = Instant::now();
+    prepared.cfg.metrics.query_submitted();
+    let res = fetch(session, registered, prepared, term, query);
+    prepared.cfg.metrics.query_completed(start.elapsed(), res.is_ok());
+    res
+}
+
+fn fetch(session: &mut Session,
+         registered: &SessionQueries,
+         prepared: &Prepared,
+         term: &Term,
+         query: &str)
+         -> Result<Value>
+{
+    let token = session.next_token();
+    let qry = wrap_query(QueryType::START, Some(term.encode()), encode_opts(prepared));
+    registered.write(session, token, &qry)?;
+    let mut values = Vec::new();
+    loop {
+        let resp = read_query(session, token)?;
+        let result: ReqlResponse = from_slice(&resp[..])?;
+        let respt = response_type(&result, query)?;
+        if respt != ResponseType::SUCCESS_PARTIAL {
+            registered.finish(token);
+            return Ok(result_value(respt, result.r, values));
+        }
+        if let Value::Array(data) = result.r {
+            values.extend(data);
+        }
+        // Ask for the rest of the sequence
+        let qry = wrap_query(QueryType::CONTINUE, None, None);
+        registered.write(session, token, &qry)?;
+    }
+}
+
+fn encode_opts(prepared: &Prepared) -> Option<String>
+{
+    let res = prepared.opts.encode();
+    if res.is_empty() { None } else { Some(res) }
+}
+
+// The value of a finished query, given what it returned so far
+fn result_value(respt: ResponseType, value: Value, mut values: Vec<Value>) -> Value
+{
+    match respt {
+        ResponseType::SUCCESS_SEQUENCE => {
+            if let Value::Array(data) = value {
+                values.extend(data);
+            }
+            Value::Array(values)
+        }
+        ResponseType::SUCCESS_ATOM => {
+            match value {
+                Value::Array(mut data) => {
+                    if data.len() == 1 {
+                        data.remove(0)
+                    } else {
+                        Value::Array(data)
+                    }
+                }
+                data => data,
+            }
+        }
+        _ => value,
+    }
+}
@@ -1,30 +1,49 @@
 mod pool;
 mod request;
 mod handshake;
+mod batch;
+mod index;
+mod many;
+mod top_n;
+mod response;
 
+pub use self::batch::insert_batched;
+pub use self::many::run_many;
+pub use self::top_n::top_n_feed;
+use self::index::validate_indexes;
 
-use {Client, Config, Connection, Document, IntoArg, Opts, Request, Response, Result, Run, Server,
-     Session, SessionManager};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use {Arg, Backlog, Client, Config, Connection, ConnectionHandle, ConnectionListener, Document,
+     FeedItem, IntoArg, Metrics, Opts, Overflow, PoolState, Prepared, Queries, Request, ResilientFeed,
+     Response, Result, Run, RunOpts, Server, ServerView, Session, SessionManager, SessionQueries,
+     SharedConnection, var_id};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use errors::*;
 use futures::{Async, Poll, Sink, Stream};
-use futures::sync::mpsc;
+use futures::sync::{mpsc, oneshot};
 use ordermap::OrderMap;
-use parking_lot::RwLock;
-use protobuf::ProtobufEnum;
-use ql2::proto::{Datum, Term};
+use parking_lot::{Condvar, Mutex, RwLock};
+use protobuf::{ProtobufEnum, RepeatedField};
+use ql2::proto::{Datum, Datum_DatumType as DatumType, Term, Term_AssocPair as TermPair,
+                 Term_TermType as TermType};
 use ql2::proto::Query_QueryType as QueryType;
 use r2d2;
 use reql_types::{Change, ServerStatus};
 use serde::de::DeserializeOwned;
+use serde_json::Value;
 use slog::Logger;
 use std::{error, thread};
-use std::cmp::Ordering;
+use std::cmp::{self, Ordering};
+use std::collections::VecDeque;
 use std::io::{self, Read, Write};
+use std::mem;
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
 use std::time::{Duration, Instant};
-use tokio_core::reactor::Remote;
+use tokio_core::reactor::{Core, Remote};
+use types::{Encode, FromJson};
 use uuid::Uuid;
 
 lazy_static! {
@@ -33,39 +52,169 @@ lazy_static! {
 }
 
 const CHANNEL_SIZE: usize = 1024;
+const MIN_IDLE: u32 = 5;
+// How many queries can fail in a row because of a server before it's
+// avoided, and for how long
+const BREAKER_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN: u64 = 30;
+// The largest query the server accepts
+const MAX_QUERY_SIZE: usize = 64 * 1024 * 1024;
+// How often, in milliseconds, a query with its own `checkout_timeout` looks
+// for a free session
+const CHECKOUT_INTERVAL: u64 = 5;
 
 pub fn connect<A: IntoArg>(client: &Client, args: A) -> Result<Connection>
+{
+    open(client, "connect", args.into_arg(), None)
+}
+
+pub fn connect_addrs<A: IntoArg>(client: &Client, addrs: Vec<SocketAddr>, args: A) -> Result<Connection>
+{
+    if addrs.is_empty() {
+        let msg = String::from("`connect_addrs` requires at least one address");
+        return Err(DriverError::Other(msg))?;
+    }
+    open(client, "connect_addrs", args.into_arg(), Some(addrs))
+}
+
+fn open(client: &Client, name: &'static str, arg: Arg, addrs: Option<Vec<SocketAddr>>) -> Result<Connection>
 {
     if let Err(ref error) = client.term {
         return Err(error.clone());
     }
-    let arg = args.into_arg();
     let aterm = arg.term?;
     let conn = Connection(Uuid::new_v4());
-    let logger = client.logger.new(o!("command" => "connect"));
-    let query = format!("{}.connect({})", client.query, arg.string);
+    let logger = client.logger.new(o!("command" => name));
+    let query = format!("{}.{}({})", client.query, name, arg.string);
     debug!(logger, "{}", query);
     info!(logger, "creating connection pool...");
     match arg.remote {
-        Some(remote) => conn.set_config(aterm, remote, logger.clone())?,
+        Some(remote) => conn.set_config(aterm, addrs, remote, arg.metrics, arg.listener, logger.clone())?,
         None => {
-            return Err(io_error("a futures handle is required for `connect`"))?;
-        }
-    }
-    conn.set_latency()?;
-    let config = r2d2::Config::builder()
-        .pool_size(144)
-        .idle_timeout(Some(Duration::from_secs(120)))
-        .max_lifetime(Some(Duration::from_secs(86400)))
-        .min_idle(Some(5))
-        .connection_timeout(Duration::from_secs(3))
-        .build();
-    let session = SessionManager(conn);
-    let r2d2 = r2d2::Pool::new(config, session)
-        .map_err(|err| io_error(err))?;
-    conn.set_pool(r2d2);
+            let msg = String::from("a futures handle is required for `connect`");
+            return Err(DriverError::MissingHandle(msg))?;
+        }
+    }
+    conn.build_pool(&logger)?;
     info!(logger, "connection pool created successfully");
-    conn.maintain();
+    if conn.config().opts.ensure_db {
+        if let Err(error) = ensure_db(conn, &logger) {
+            conn.close();
+            return Err(error);
+        }
+    }
+    if conn.config().opts.discover_hosts {
+        if conn.config().opts.watch_servers {
+            conn.maintain();
+        } else {
+            conn.discover(&logger);
+        }
+    } else {
+        info!(logger, "host discovery disabled, using the configured servers only");
+    }
+    Ok(conn)
+}
+
+// Create the default database if it doesn't exist yet
+fn ensure_db(conn: Connection, logger: &Logger) -> Result<()>
+{
+    let db = conn.default_db();
+    let r = Client::new().with_logger(logger.clone());
+    let dbs = r.db_list().run::<Vec<String>>(conn)?.single()?.unwrap_or_default();
+    if dbs.iter().any(|name| *name == db) {
+        return Ok(());
+    }
+    info!(logger, "creating database `{}`", db);
+    created(r.db_create(db.as_str()).run::<Value>(conn)?.single()).map(|_| ())
+}
+
+pub fn db_create_if_not_exists<A: IntoArg>(client: &Client, args: A, name: &str) -> Result<bool>
+{
+    created(client.db_create(name).run::<Value>(args)?.single())
+}
+
+pub fn table_create_if_not_exists<A: IntoArg>(client: &Client, args: A, name: &str) -> Result<bool>
+{
+    created(client.table_create(name).run::<Value>(args)?.single())
+}
+
+pub fn databases<A: IntoArg>(client: &Client, args: A) -> Result<Vec<String>>
+{
+    sorted(client.db_list().run::<Vec<String>>(args)?)
+}
+
+pub fn tables<A: IntoArg>(client: &Client, args: A) -> Result<Vec<String>>
+{
+    sorted(client.table_list().run::<Vec<String>>(args)?)
+}
+
+// `is_empty` stops reading the index at the first match, unlike `count`
+pub fn exists<A, V>(client: &Client, args: A, index: &str, value: V) -> Result<bool>
+    where A: IntoArg,
+          V: IntoArg
+{
+    let query = client.get_all(args!(value, {index: index})).is_empty();
+    match query.run::<bool>(args)?.single()? {
+        Some(empty) => Ok(!empty),
+        None => Ok(false),
+    }
+}
+
+fn sorted(res: Response<Vec<String>>) -> Result<Vec<String>>
+{
+    let mut names = res.single()?.unwrap_or_default();
+    names.sort();
+    Ok(names)
+}
+
+// Whether `db_create` or `table_create` created anything, without failing
+// if it already exists. Creating it straight away, rather than listing
+// first, also covers someone else creating it in the meantime.
+fn created(res: Result<Option<Value>>) -> Result<bool>
+{
+    let error = match res {
+        Ok(_) => return Ok(true),
+        Err(error) => error,
+    };
+    if let Error::Runtime(ref runtime) = error {
+        if let RuntimeError::Availability(AvailabilityError::OpFailed(ref msg)) = **runtime {
+            if msg.contains("already exists") {
+                return Ok(false);
+            }
+        }
+    }
+    Err(error)
+}
+
+pub fn connect_sync<A: IntoArg>(client: &Client, args: A) -> Result<Connection>
+{
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    let (stop, stopped) = oneshot::channel::<()>();
+    thread::spawn(move || {
+                      let mut core = match Core::new() {
+                          Ok(core) => core,
+                          Err(error) => {
+                              let _ = tx.send(Err(error));
+                              return;
+                          }
+                      };
+                      let _ = tx.send(Ok(core.remote()));
+                      // Runs until `stop` is dropped
+                      let _ = core.run(stopped);
+                  });
+    let remote = match rx.recv() {
+        Ok(remote) => remote?,
+        Err(_) => {
+            let msg = String::from("the reactor thread exited before it started");
+            return Err(DriverError::Other(msg))?;
+        }
+    };
+    let mut arg = args.into_arg();
+    arg.remote = Some(remote);
+    let conn = connect(client, arg)?;
+    if let Some(config) = CONFIG.write().get_mut(&conn) {
+        config.reactor = Some(Arc::new(stop));
+    }
     Ok(conn)
 }
 
@@ -73,61 +222,182 @@ impl<A: IntoArg> Run<A> for Client
 {
     fn run<T: DeserializeOwned + Send + 'static>(&self, args: A) -> Result<Response<T>>
     {
-        let cterm = match self.term {
-            Ok(ref term) => term.clone(),
-            Err(ref error) => {
-                return Err(error.clone());
-            }
-        };
-        let arg = args.into_arg();
-        let aterm = arg.term?;
-        let logger = self.logger.new(o!("command" => "run"));
-        let query = format!("{}.run({})", self.query, arg.string);
-        debug!(logger, "{}", query);
-        let conn = match arg.pool {
-            Some(conn) => conn.clone(),
-            None => {
-                let msg = String::from("`run` requires a connection");
-                return Err(DriverError::Other(msg))?;
-            }
-        };
-        let pool = match POOL.read().get(&conn) {
-            Some(pool) => pool.clone(),
-            None => {
-                let msg = String::from("bug: connection not in POOL");
-                return Err(DriverError::Other(msg))?;
-            }
-        };
-        let cfg = match CONFIG.read().get(&conn) {
-            Some(cfg) => cfg.clone(),
-            None => {
-                return Err(io_error("a tokio handle is required"))?;
-            }
-        };
-        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
-        //let remote = cfg.remote.clone();
-        // @TODO spawning a thread per query is less than ideal. Ideally we will
-        // need first class support for Tokio to get rid of this.
-        ::std::thread::spawn(move || {
-                                 let req = Request {
-                                     term: cterm,
-                                     opts: aterm,
-                                     pool: pool,
-                                     cfg: cfg,
-                                     tx: tx,
-                                     write: true,
-                                     retry: false,
-                                     logger: logger,
-                                 };
-                                 req.submit();
-                             });
-        Ok(Response {
-               done: false,
-               rx: rx,
-           })
+        run(self, args.into_arg(), None, true)
     }
 }
 
+pub fn run_timeout<A, T>(client: &Client, args: A, timeout: Duration) -> Result<Response<T>>
+    where A: IntoArg,
+          T: DeserializeOwned + Send + 'static
+{
+    run(client, args.into_arg(), Some(timeout), true)
+}
+
+pub fn run_resilient<A, T>(client: &Client, args: A) -> Result<ResilientFeed<T>>
+    where A: IntoArg,
+          T: DeserializeOwned + Send + 'static
+{
+    let arg = args.into_arg();
+    let response = run(client, arg.clone(), None, true)?;
+    Ok(ResilientFeed {
+           client: client.clone(),
+           arg: arg,
+           response: response,
+       })
+}
+
+// Get the connection and the options of a query ready
+//
+// This is shared by `run` and the commands that run queries on a session of
+// their own, so they all honour the same options.
+fn prepare(client: &Client, cmd: &str, arg: Arg) -> Result<Prepared>
+{
+    let mut opts = arg.term?;
+    merge_run_opts(&mut opts, &client.run_opts)?;
+    let run_opts = take_run_opts(&mut opts)?;
+    let conn = match arg.pool {
+        Some(conn) => conn,
+        None => {
+            let msg = format!("`{}` requires a connection", cmd);
+            return Err(DriverError::MissingConnection(msg))?;
+        }
+    };
+    let pool = match POOL.read().get(&conn) {
+        Some(pool) => pool.clone(),
+        None => {
+            let msg = String::from("the connection is closed");
+            return Err(DriverError::ConnectionClosed(msg))?;
+        }
+    };
+    let cfg = match CONFIG.read().get(&conn) {
+        Some(cfg) => cfg.clone(),
+        None => {
+            let msg = String::from("the connection is closed");
+            return Err(DriverError::ConnectionClosed(msg))?;
+        }
+    };
+    if cfg.draining {
+        let msg = String::from("the connection is draining");
+        return Err(DriverError::ConnectionClosed(msg))?;
+    }
+    let db = set_default_opts(&mut opts, &cfg.opts)?;
+    Ok(Prepared {
+           conn: conn,
+           pool: pool,
+           cfg: cfg,
+           opts: opts,
+           run_opts: run_opts,
+           db: db,
+       })
+}
+
+// Check a query before it's submitted, making it reproducible if the
+// connection needs it to be
+fn check_query(prepared: &Prepared, term: &mut Term, query: &str) -> Result<()>
+{
+    wrap_implicit_var(term, false)?;
+    check_bounded(term)?;
+    check_safe_mode(&prepared.cfg.opts, &prepared.run_opts, term)?;
+    if prepared.cfg.opts.reproducible {
+        make_reproducible(term, query, &mut 0)?;
+    }
+    if prepared.cfg.opts.validate_indexes {
+        validate_indexes(prepared.conn, &prepared.db, term)?;
+    }
+    Ok(())
+}
+
+pub fn run_term<A, T>(client: &Client, args: A, term: Term) -> Result<Response<T>>
+    where A: IntoArg,
+          T: DeserializeOwned + Send + 'static
+{
+    let mut cmd = client.clone();
+    cmd.query = term.encode();
+    cmd.term = Ok(term);
+    run(&cmd, args.into_arg(), None, true)
+}
+
+// Queries are registered so `drain` waits for them, except for the driver's
+// own changefeed on `server_status` which never finishes
+fn run<T>(client: &Client, arg: Arg, timeout: Option<Duration>, register: bool) -> Result<Response<T>>
+    where T: DeserializeOwned + Send + 'static
+{
+    let mut cterm = match client.term {
+        Ok(ref term) => term.clone(),
+        Err(ref error) => {
+            return Err(error.clone());
+        }
+    };
+    let logger = client.logger.new(o!("command" => "run"));
+    let query = format!("{}.run({})", client.query, arg.string);
+    debug!(logger, "{}", query);
+    let prepared = prepare(client, "run", arg)?;
+    check_query(&prepared, &mut cterm, &client.query)?;
+    let Prepared { conn, pool, cfg, opts: aterm, run_opts, .. } = prepared;
+    cfg.metrics.query_submitted();
+    // A timeout given to `run` takes precedence over the connection's
+    let timeout = timeout.or(cfg.opts.query_timeout);
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (tx, rx) = mpsc::channel(run_opts.buffer_size);
+    let profile = Arc::new(RwLock::new(None));
+    let req_profile = profile.clone();
+    let backlog = match run_opts.overflow {
+        Overflow::Block => None,
+        overflow => {
+            Some(Arc::new(Backlog {
+                              docs: Mutex::new(VecDeque::new()),
+                              size: run_opts.buffer_size,
+                              overflow: overflow,
+                              dropped: AtomicUsize::new(0),
+                          }))
+        }
+    };
+    let req_backlog = backlog.clone();
+    let id = Uuid::new_v4();
+    let running = Arc::new(Mutex::new(None));
+    if register {
+        cfg.queries.running.lock().insert(id, running.clone());
+    }
+    //let remote = cfg.remote.clone();
+    // @TODO spawning a thread per query is less than ideal. Ideally we will
+    // need first class support for Tokio to get rid of this.
+    ::std::thread::spawn(move || {
+                             let req = Request {
+                                 query: query,
+                                 term: cterm,
+                                 opts: aterm,
+                                 pool: pool,
+                                 cfg: cfg,
+                                 // A single blocking sender so the thread stops
+                                 // reading from the server when the buffer is full
+                                 tx: tx.wait(),
+                                 write: true,
+                                 retry: false,
+                                 failed: false,
+                                 received: false,
+                                 dropped: false,
+                                 deadline: deadline,
+                                 checkout_timeout: run_opts.checkout_timeout,
+                                 idle_timeout: run_opts.idle_timeout,
+                                 token: 0,
+                                 profile: req_profile,
+                                 backlog: req_backlog,
+                                 id: id,
+                                 running: running,
+                                 conn: conn,
+                                 server: None,
+                                 logger: logger,
+                             };
+                             req.submit();
+                         });
+    Ok(Response {
+           done: false,
+           rx: rx,
+           profile: profile,
+           backlog: backlog,
+       })
+}
+
 impl<T: DeserializeOwned + Send> Stream for Response<T>
 {
     type Item = Option<Document<T>>;
@@ -138,6 +408,32 @@ impl<T: DeserializeOwned + Send> Stream for Response<T>
         if self.done {
             return Ok(Async::Ready(None));
         }
+        if let Some(backlog) = self.backlog.clone() {
+            loop {
+                if let Some(res) = backlog.docs.lock().pop_front() {
+                    return match res {
+                               Ok(data) => Ok(Async::Ready(Some(data))),
+                               Err(error) => Err(error),
+                           };
+                }
+                match self.rx.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    // Only wake ups are sent through the channel
+                    Ok(Async::Ready(Some(_))) => continue,
+                    // The results are added before the channel is closed
+                    Ok(Async::Ready(None)) if !backlog.docs.lock().is_empty() => continue,
+                    Ok(Async::Ready(None)) => {
+                        self.done = true;
+                        return Ok(Async::Ready(None));
+                    }
+                    Err(_) => {
+                        self.done = true;
+                        let msg = String::from("an error occured while processing the stream");
+                        return Err(DriverError::Other(msg))?;
+                    }
+                }
+            }
+        }
         match self.rx.poll() {
             Ok(Async::NotReady) => Ok(Async::NotReady),
             Ok(Async::Ready(Some(res))) => {
@@ -159,6 +455,154 @@ impl<T: DeserializeOwned + Send> Stream for Response<T>
     }
 }
 
+impl<T: DeserializeOwned + Send + 'static> Stream for ResilientFeed<T>
+{
+    type Item = FeedItem<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error>
+    {
+        match self.response.poll() {
+            Ok(Async::Ready(Some(doc))) => Ok(Async::Ready(Some(FeedItem::Doc(doc)))),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(ref error) if is_connection_error(error) => {
+                warn!(self.client.logger, "changefeed interrupted, restarting: {:?}", error);
+                self.response = self.client.run::<T>(self.arg.clone())?;
+                Ok(Async::Ready(Some(FeedItem::Gap)))
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+fn is_connection_error(error: &Error) -> bool
+{
+    if let Error::Driver(ref error) = *error {
+        match **error {
+            DriverError::Io(_) |
+            DriverError::GetTimeout(_) |
+            DriverError::PoolExhausted(_) |
+            DriverError::ServerClosed(_) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn resolve_cluster(hosts: &[String]) -> Result<OrderMap<String, Server>>
+{
+    let mut cluster = OrderMap::new();
+    for host in hosts {
+        let addresses = host.to_socket_addrs()
+            .or_else(|_| {
+                         let host = format!("{}:{}", host, 28015);
+                         host.to_socket_addrs()
+                     })?;
+        let server = Server::new(host, addresses.collect());
+        cluster.insert(host.to_owned(), server);
+    }
+    Ok(cluster)
+}
+
+// Open the idle sessions of a new pool upfront
+fn warm_up(pool: &r2d2::Pool<SessionManager>, logger: &Logger) -> Result<()>
+{
+    debug!(logger, "opening {} sessions", MIN_IDLE);
+    let mut sessions = Vec::new();
+    let mut last_error = None;
+    for _ in 0..MIN_IDLE {
+        match pool.get() {
+            Ok(session) => sessions.push(session),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    if sessions.is_empty() {
+        if let Some(error) = last_error {
+            return Err(pool_exhausted(error))?;
+        }
+    }
+    info!(logger, "{} sessions ready", sessions.len());
+    // Dropping the sessions returns them to the pool
+    Ok(())
+}
+
+fn pool_exhausted(error: r2d2::GetTimeout) -> DriverError
+{
+    DriverError::PoolExhausted(format!("failed to get a session from the pool: {}", error))
+}
+
+// Check a session out of the pool, waiting at most `timeout` if one is given
+// instead of the connection's `checkout_timeout`
+fn checkout<M>(pool: &r2d2::Pool<M>, timeout: Option<Duration>) -> Result<r2d2::PooledConnection<M>>
+    where M: r2d2::ManageConnection
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return pool.get().map_err(|error| Error::from(pool_exhausted(error))),
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(session) = pool.try_get() {
+            return Ok(session);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            let msg = format!("no session became available within {:?}", timeout);
+            return Err(DriverError::PoolExhausted(msg))?;
+        }
+        // Sessions are returned to the pool as queries finish
+        thread::sleep(cmp::min(deadline - now, Duration::from_millis(CHECKOUT_INTERVAL)));
+    }
+}
+
+impl SessionQueries
+{
+    // Register the queries about to be run on `session`
+    fn new(queries: Arc<Queries>, session: &Session) -> SessionQueries
+    {
+        let id = Uuid::new_v4();
+        let stream = session.stream.try_clone().ok().map(|stream| (Vec::new(), stream));
+        let running = Arc::new(Mutex::new(stream));
+        queries.running.lock().insert(id, running.clone());
+        SessionQueries {
+            queries: queries,
+            id: id,
+            running: running,
+        }
+    }
+
+    // Write to the session, keeping track of the query's token until it's
+    // finished so `drain` can stop it
+    fn write(&self, session: &mut Session, token: u64, query: &str) -> Result<()>
+    {
+        let mut running = self.running.lock();
+        write_query(session, token, query)?;
+        if let Some((ref mut tokens, _)) = *running {
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self, token: u64)
+    {
+        if let Some((ref mut tokens, _)) = *self.running.lock() {
+            tokens.retain(|running| *running != token);
+        }
+    }
+}
+
+impl Drop for SessionQueries
+{
+    fn drop(&mut self)
+    {
+        self.queries.running.lock().remove(&self.id);
+        self.queries.done.notify_all();
+    }
+}
+
 fn io_error<T>(err: T) -> io::Error
     where T: Into<Box<error::Error + Send + Sync>>
 {
@@ -171,6 +615,7 @@ impl Default for Opts
     {
         Opts {
             db: "test".into(),
+            servers: Vec::new(),
             user: "admin".into(),
             password: String::new(),
             // @TODO number of retries doesn't mean much
@@ -182,11 +627,29 @@ impl Default for Opts
             // what it does.
             retries: 5,
             reproducible: false,
+            validate_indexes: false,
+            discover_hosts: true,
+            watch_servers: true,
+            eager: false,
+            identifier_format: None,
+            query_timeout: None,
+            checkout_timeout: Duration::from_secs(3),
+            keepalive: Some(Duration::from_secs(60)),
+            ensure_db: false,
+            safe_mode: false,
             tls: None,
         }
     }
 }
 
+struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+struct NoListener;
+
+impl ConnectionListener for NoListener {}
+
 impl Ord for Server
 {
     fn cmp(&self, other: &Server) -> Ordering
@@ -226,27 +689,489 @@ fn find_datum(mut term: Term) -> Vec<Datum>
     res
 }
 
+// Wrap the arguments using `r.row` in functions of the current document
+//
+// Like the official drivers, an argument of a command taking a function that
+// refers to `r.row` is sent as a function of one parameter, which the server
+// binds `r.row` to. When one of those arguments contains another query using
+// `r.row` there is no way to tell which document each one refers to.
+fn wrap_implicit_var(term: &mut Term, nested: bool) -> Result<()>
+{
+    let takes_func = match term.get_field_type() {
+        TermType::FILTER |
+        TermType::MAP |
+        TermType::CONCAT_MAP |
+        TermType::ORDER_BY |
+        TermType::GROUP |
+        TermType::CONTAINS |
+        TermType::UPDATE |
+        TermType::REPLACE |
+        TermType::FOR_EACH => true,
+        _ => false,
+    };
+    for (i, arg) in term.mut_args().iter_mut().enumerate() {
+        // The first argument is the sequence the command is called on
+        let implicit = takes_func && i > 0 && has_implicit_var(arg);
+        if implicit && nested {
+            let msg = String::from("`r.row` can't be used in nested queries, use a closure instead");
+            return Err(DriverError::Other(msg))?;
+        }
+        wrap_implicit_var(arg, nested || implicit)?;
+        if implicit {
+            let body = mem::replace(arg, Term::new());
+            *arg = implicit_func(body);
+        }
+    }
+    for pair in term.mut_optargs().iter_mut() {
+        wrap_implicit_var(pair.mut_val(), nested)?;
+    }
+    Ok(())
+}
+
+// A function of one parameter with `body` as its body
+fn implicit_func(body: Term) -> Term
+{
+    let mut id = Datum::new();
+    id.set_field_type(DatumType::R_NUM);
+    id.set_r_num(var_id() as f64);
+    let mut params = Datum::new();
+    params.set_field_type(DatumType::R_ARRAY);
+    params.set_r_array(RepeatedField::from_vec(vec![id]));
+    let mut datum = Term::new();
+    datum.set_field_type(TermType::DATUM);
+    datum.set_datum(params);
+    let mut func = Term::new();
+    func.set_field_type(TermType::FUNC);
+    func.set_args(RepeatedField::from_vec(vec![datum, body]));
+    func
+}
+
+fn has_implicit_var(term: &Term) -> bool
+{
+    match term.get_field_type() {
+        TermType::IMPLICIT_VAR => true,
+        // Closures bind their own parameters
+        TermType::FUNC => false,
+        _ => {
+            term.get_args().iter().any(has_implicit_var) ||
+            term.get_optargs().iter().any(|pair| has_implicit_var(pair.get_val()))
+        }
+    }
+}
+
+// Reject queries that return all of an infinite `r.range()`
+//
+// Such a query never completes, so anything collecting its results would
+// wait forever. Commands chained onto the range are followed until one of
+// them bounds the sequence.
+fn check_bounded(term: &Term) -> Result<()>
+{
+    match term.get_field_type() {
+        TermType::LIMIT |
+        TermType::NTH |
+        TermType::IS_EMPTY => Ok(()),
+        TermType::SLICE if term.get_args().len() > 2 => Ok(()),
+        TermType::RANGE if term.get_args().is_empty() => {
+            let msg = String::from("`r.range()` without arguments is infinite, bound it with `limit`");
+            Err(DriverError::Other(msg))?
+        }
+        _ => {
+            match term.get_args().first() {
+                Some(arg) => check_bounded(arg),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+// Refuse writes on whole tables in `safe_mode`, unless the query allows them
+fn check_safe_mode(opts: &Opts, run_opts: &RunOpts, term: &Term) -> Result<()>
+{
+    if opts.safe_mode && !run_opts.allow_full_table_write {
+        check_full_table_write(term)?;
+    }
+    Ok(())
+}
+
+// Reject writes made directly on a table, without any selection in between,
+// since they change every document in it
+fn check_full_table_write(term: &Term) -> Result<()>
+{
+    match term.get_field_type() {
+        TermType::DELETE |
+        TermType::UPDATE |
+        TermType::REPLACE => {
+            if let Some(arg) = term.get_args().first() {
+                if arg.get_field_type() == TermType::TABLE {
+                    let msg = String::from("refusing unfiltered table-wide write; set allow_full_table_write");
+                    return Err(DriverError::Other(msg))?;
+                }
+            }
+        }
+        _ => {}
+    }
+    // Writes can be nested anywhere, like in `do_`, `for_each` or `branch`
+    for arg in term.get_args() {
+        check_full_table_write(arg)?;
+    }
+    for pair in term.get_optargs() {
+        check_full_table_write(pair.get_val())?;
+    }
+    Ok(())
+}
+
+// Make sure a query returns the same results every time it runs
+//
+// Calls to `r.uuid()` without a name are given one derived from the query
+// and their position in it, so the same query always generates the same UUIDs.
+// There is no way to do the same for `r.now()` and `r.random()` so those are
+// rejected instead.
+fn make_reproducible(term: &mut Term, seed: &str, count: &mut usize) -> Result<()>
+{
+    match term.get_field_type() {
+        TermType::UUID => {
+            if term.get_args().is_empty() {
+                *count += 1;
+                let name = format!("{}#{}", seed, count);
+                term.mut_args().push(Term::from_json(name)?);
+            }
+        }
+        TermType::NOW => {
+            let msg = String::from("`r.now()` can't be used when `reproducible` is set");
+            return Err(DriverError::Other(msg))?;
+        }
+        TermType::RANDOM => {
+            let msg = String::from("`r.random()` can't be used when `reproducible` is set");
+            return Err(DriverError::Other(msg))?;
+        }
+        _ => {}
+    }
+    for arg in term.mut_args().iter_mut() {
+        make_reproducible(arg, seed, count)?;
+    }
+    for pair in term.mut_optargs().iter_mut() {
+        make_reproducible(pair.mut_val(), seed, count)?;
+    }
+    Ok(())
+}
+
+// Remove the options meant for the driver so they are not sent to the server
+fn take_run_opts(term: &mut Term) -> Result<RunOpts>
+{
+    let mut opts = RunOpts {
+        buffer_size: CHANNEL_SIZE,
+        checkout_timeout: None,
+        overflow: Overflow::Block,
+        allow_full_table_write: false,
+        idle_timeout: None,
+    };
+
+    let optargs = term.take_optargs().into_vec();
+    for mut arg in optargs {
+        if arg.get_key() == "buffer_size" {
+            let key = arg.take_key();
+            let val = find_datum(arg.take_val());
+            opts.buffer_size = take_u64(&key, val)? as usize;
+        } else if arg.get_key() == "checkout_timeout" {
+            let key = arg.take_key();
+            let val = find_datum(arg.take_val());
+            let millis = take_u64(&key, val)?;
+            opts.checkout_timeout = Some(Duration::from_millis(millis));
+        } else if arg.get_key() == "idle_timeout" {
+            let key = arg.take_key();
+            let val = find_datum(arg.take_val());
+            let millis = take_u64(&key, val)?;
+            if millis == 0 {
+                let msg = String::from("`idle_timeout` must be greater than 0");
+                return Err(DriverError::Other(msg))?;
+            }
+            opts.idle_timeout = Some(Duration::from_millis(millis));
+        } else if arg.get_key() == "allow_full_table_write" {
+            let key = arg.take_key();
+            let val = find_datum(arg.take_val());
+            opts.allow_full_table_write = take_bool(&key, val)?;
+        } else if arg.get_key() == "array_limit" {
+            // This one is meant for the server, which needs a positive limit
+            let limit = take_u64(arg.get_key(), find_datum(arg.get_val().clone()))?;
+            if limit == 0 {
+                let msg = String::from("`array_limit` must be greater than 0");
+                return Err(DriverError::Other(msg))?;
+            }
+            term.mut_optargs().push(arg);
+        } else if arg.get_key() == "overflow" {
+            let key = arg.take_key();
+            let val = find_datum(arg.take_val());
+            opts.overflow = match take_string(&key, val)?.as_str() {
+                "block" => Overflow::Block,
+                "drop_oldest" => Overflow::DropOldest,
+                "error" => Overflow::Error,
+                _ => {
+                    let msg = String::from("`overflow` must be either \"block\", \"drop_oldest\" or \"error\"");
+                    return Err(DriverError::Other(msg))?;
+                }
+            };
+        } else {
+            term.mut_optargs().push(arg);
+        }
+    }
+
+    Ok(opts)
+}
+
+// Add the client's default run options that weren't passed to this run
+fn merge_run_opts(term: &mut Term, defaults: &Result<Term>) -> Result<()>
+{
+    let defaults = match *defaults {
+        Ok(ref defaults) => defaults,
+        Err(ref error) => return Err(error.clone()),
+    };
+    for pair in defaults.get_optargs() {
+        let given = term.get_optargs().iter().any(|p| p.get_key() == pair.get_key());
+        if !given {
+            term.mut_optargs().push(pair.clone());
+        }
+    }
+    Ok(())
+}
+
+// Add the connection defaults for the global options not given to `run`
+//
+// Returns the name of the database the query runs against unless it
+// says otherwise using `r.db()`.
+fn set_default_opts(term: &mut Term, opts: &Opts) -> Result<String>
+{
+    let mut db = None;
+    for pair in term.mut_optargs().iter_mut() {
+        if pair.get_key() != "db" {
+            continue;
+        }
+        let val = optarg_val(pair);
+        let is_name = val.get_field_type() == TermType::DATUM &&
+                      val.get_datum().get_field_type() == DatumType::R_STR;
+        // The server expects a database rather than its name
+        if is_name {
+            let name = val.get_datum().get_r_str().to_owned();
+            *val = db_term(&name)?;
+            db = Some(name);
+        } else if val.get_field_type() == TermType::DB {
+            for mut datum in find_datum(val.clone()) {
+                db = Some(datum.take_r_str());
+            }
+        }
+    }
+    let db = match db {
+        Some(db) => db,
+        None => {
+            let pair = Arg::create_term_pair("db", db_term(&opts.db)?)?;
+            term.mut_optargs().push(pair);
+            opts.db.clone()
+        }
+    };
+    if let Some(ref format) = opts.identifier_format {
+        let is_set = term.get_optargs()
+            .iter()
+            .any(|pair| pair.get_key() == "identifier_format");
+        if !is_set {
+            let pair = Arg::create_term_pair("identifier_format", format.as_str())?;
+            term.mut_optargs().push(pair);
+        }
+    }
+    Ok(db)
+}
+
+// The value of an optional argument. `args!` wraps it in terms without a
+// type, which are left out when the query is encoded.
+fn optarg_val(pair: &mut TermPair) -> &mut Term
+{
+    let mut val = pair.mut_val();
+    while !val.has_field_type() && !val.has_datum() && val.get_args().len() == 1 {
+        let wrapper = val;
+        val = &mut wrapper.mut_args()[0];
+    }
+    val
+}
+
+fn db_term(name: &str) -> Result<Term>
+{
+    let mut term = Term::new();
+    term.set_field_type(TermType::DB);
+    term.mut_args().push(Term::from_json(name)?);
+    Ok(term)
+}
+
 fn take_string(key: &str, val: Vec<Datum>) -> Result<String>
 {
     for mut datum in val {
         return Ok(datum.take_r_str());
     }
-    Err(DriverError::Other(format!("`{}` must be a string", key)))?
-}
+    Err(DriverError::Other(format!("`{}` must be a string", key)))?
+}
+
+fn take_bool(key: &str, val: Vec<Datum>) -> Result<bool>
+{
+    for datum in val {
+        return Ok(datum.get_r_bool());
+    }
+    Err(DriverError::Other(format!("`{}` must be a boolean", key)))?
+}
+
+fn take_u64(key: &str, val: Vec<Datum>) -> Result<u64>
+{
+    for datum in val {
+        let num = datum.get_r_num();
+        if datum.get_field_type() == DatumType::R_NUM && num >= 0.0 && num.fract() == 0.0 {
+            return Ok(num as u64);
+        }
+    }
+    Err(DriverError::Other(format!("`{}` must be a positive integer", key)))?
+}
+
+impl Connection
+{
+    /// Get a snapshot of the servers this connection currently knows about
+    ///
+    /// This is the same view of the cluster the driver uses to pick a server
+    /// when opening new sessions. It is kept up to date in the background as
+    /// servers join and leave the cluster.
+    pub fn cluster(&self) -> Vec<ServerView>
+    {
+        match CONFIG.read().get(self) {
+            Some(config) => config.cluster.values().map(Server::view).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get a snapshot of the state of the connection pool
+    ///
+    /// Returns `None` if the connection is closed.
+    pub fn pool_state(&self) -> Option<PoolState>
+    {
+        POOL.read().get(self).map(|pool| {
+                                      let state = pool.state();
+                                      PoolState {
+                                          connections: state.connections,
+                                          idle_connections: state.idle_connections,
+                                      }
+                                  })
+    }
+
+    /// The version of RethinkDB the connection is talking to
+    ///
+    /// This is the version the server reported when the last session was
+    /// opened, like `"2.3.6~0xenial"`, or `None` if no session
+    /// has been opened yet.
+    pub fn server_version(&self) -> Option<String>
+    {
+        match CONFIG.read().get(self) {
+            Some(config) => config.server_version.clone(),
+            None => None,
+        }
+    }
+
+    /// The database queries run against by default
+    ///
+    /// This is the `db` given to `connect`, or `test` if none was given.
+    pub fn default_db(&self) -> String
+    {
+        match CONFIG.read().get(self) {
+            Some(config) => config.opts.db.clone(),
+            None => Opts::default().db,
+        }
+    }
+
+    /// Replace the connection pool with a new one
+    ///
+    /// The servers given to `connect` are resolved again and their latency
+    /// measured before the new pool is built. The connection keeps its
+    /// identity and configuration so there is no need to pass a new one
+    /// around. Queries that are already running finish on the old pool.
+    pub fn reconnect(&self) -> Result<()>
+    {
+        let (servers, logger) = match CONFIG.read().get(self) {
+            Some(config) => {
+                (config.opts.servers.clone(), config.logger.new(o!("command" => "reconnect")))
+            }
+            None => {
+                let msg = String::from("the connection is closed");
+                return Err(DriverError::ConnectionClosed(msg))?;
+            }
+        };
+        info!(logger, "rebuilding connection pool...");
+        let cluster = resolve_cluster(&servers)?;
+        if let Some(config) = CONFIG.write().get_mut(self) {
+            config.cluster = cluster;
+        }
+        self.build_pool(&logger)?;
+        info!(logger, "connection pool rebuilt successfully");
+        Ok(())
+    }
+
+    /// Close the connection pool once the running queries are done
+    ///
+    /// New queries are refused straight away. The queries that are already
+    /// running, changefeeds included, are given up to `timeout` to finish,
+    /// after which they are stopped on the server. The pool is then closed
+    /// just like with `close`. This is meant for shutting down gracefully,
+    /// for example when a web server is asked to terminate.
+    pub fn drain(&self, timeout: Duration)
+    {
+        let queries = match CONFIG.write().get_mut(self) {
+            Some(config) => {
+                config.draining = true;
+                config.queries.clone()
+            }
+            None => return,
+        };
+        let deadline = Instant::now() + timeout;
+        let mut running = queries.running.lock();
+        while !running.is_empty() {
+            if queries.done.wait_until(&mut running, deadline).timed_out() {
+                break;
+            }
+        }
+        let stop = wrap_query(QueryType::STOP, None, None);
+        for running in running.values() {
+            let mut running = running.lock();
+            if let Some((ref tokens, ref mut stream)) = *running {
+                for token in tokens {
+                    let _ = stream.write_u64::<LittleEndian>(*token)
+                        .and_then(|_| stream.write_u32::<LittleEndian>(stop.len() as u32))
+                        .and_then(|_| stream.write_all(stop.as_bytes()));
+                }
+            }
+        }
+        self.close();
+    }
+
+    /// Close the connection pool
+    ///
+    /// Since copies of a connection all refer to the same pool, this
+    /// closes it for all of them. Queries that are already running are
+    /// allowed to finish but new ones will fail.
+    pub fn close(&self)
+    {
+        POOL.write().remove(self);
+        CONFIG.write().remove(self);
+    }
 
-fn take_bool(key: &str, val: Vec<Datum>) -> Result<bool>
-{
-    for datum in val {
-        return Ok(datum.get_r_bool());
+    /// Share this connection
+    ///
+    /// The pool is closed when the last clone of the returned
+    /// `SharedConnection` is dropped.
+    pub fn shared(self) -> SharedConnection
+    {
+        SharedConnection(Arc::new(ConnectionHandle(self)))
     }
-    Err(DriverError::Other(format!("`{}` must be a boolean", key)))?
-}
 
-impl Connection
-{
-    fn set_config(&self, mut term: Term, remote: Remote, logger: Logger) -> Result<()>
+    fn set_config(&self,
+                  mut term: Term,
+                  addrs: Option<Vec<SocketAddr>>,
+                  remote: Remote,
+                  metrics: Option<Arc<Metrics>>,
+                  listener: Option<Arc<ConnectionListener>>,
+                  logger: Logger)
+                  -> Result<()>
     {
-        let mut cluster = OrderMap::new();
         let mut hosts = Vec::new();
         let mut opts = Opts::default();
 
@@ -263,6 +1188,48 @@ impl Connection
                 opts.password = take_string(&key, val)?;
             } else if key == "reproducible" {
                 opts.reproducible = take_bool(&key, val)?;
+            } else if key == "validate_indexes" {
+                opts.validate_indexes = take_bool(&key, val)?;
+            } else if key == "discover_hosts" {
+                opts.discover_hosts = take_bool(&key, val)?;
+            } else if key == "watch_servers" {
+                opts.watch_servers = take_bool(&key, val)?;
+            } else if key == "ensure_db" {
+                opts.ensure_db = take_bool(&key, val)?;
+            } else if key == "safe_mode" {
+                opts.safe_mode = take_bool(&key, val)?;
+            } else if key == "eager" {
+                opts.eager = take_bool(&key, val)?;
+            } else if key == "identifier_format" {
+                let format = take_string(&key, val)?;
+                if format != "name" && format != "uuid" {
+                    let msg = String::from("`identifier_format` must be either \"name\" or \"uuid\"");
+                    return Err(DriverError::Other(msg))?;
+                }
+                opts.identifier_format = Some(format);
+            } else if key == "query_timeout" {
+                let millis = take_u64(&key, val)?;
+                // Every query would time out straight away
+                if millis == 0 {
+                    let msg = String::from("`query_timeout` must be greater than 0");
+                    return Err(DriverError::Other(msg))?;
+                }
+                opts.query_timeout = Some(Duration::from_millis(millis));
+            } else if key == "checkout_timeout" {
+                let millis = take_u64(&key, val)?;
+                opts.checkout_timeout = Duration::from_millis(millis);
+            } else if key == "keepalive" {
+                let secs = take_u64(&key, val)?;
+                opts.keepalive = if secs == 0 {
+                    None
+                } else {
+                    Some(Duration::from_secs(secs))
+                };
+            } else if key == "tls" {
+                // Ignoring this option would send credentials in plain text
+                // to a server the user expects to be authenticated
+                let msg = String::from("TLS is not supported by this driver, use a TLS proxy instead");
+                return Err(DriverError::Tls(msg))?;
             } else if key == "servers" {
                 for host in val {
                     hosts.push(take_string(&key, vec![host])?);
@@ -270,19 +1237,39 @@ impl Connection
             }
         }
 
-        if hosts.is_empty() {
-            hosts.push("localhost".into());
-        }
+        let cluster = match addrs {
+            Some(addrs) => {
+                if !hosts.is_empty() {
+                    let msg = String::from("`servers` can't be used together with explicit addresses");
+                    return Err(DriverError::Other(msg))?;
+                }
+                // These are already resolved so there is no need to look them up
+                let mut cluster = OrderMap::new();
+                for addr in addrs {
+                    let host = addr.to_string();
+                    hosts.push(host.clone());
+                    cluster.insert(host.clone(), Server::new(&host, vec![addr]));
+                }
+                cluster
+            }
+            None => {
+                if hosts.is_empty() {
+                    hosts.push("localhost".into());
+                }
+                resolve_cluster(&hosts)?
+            }
+        };
+        opts.servers = hosts;
 
-        for host in hosts {
-            let addresses = host.to_socket_addrs()
-                .or_else(|_| {
-                             let host = format!("{}:{}", host, 28015);
-                             host.to_socket_addrs()
-                         })?;
-            let server = Server::new(&host, addresses.collect());
-            cluster.insert(host, server);
-        }
+        let metrics: Arc<Metrics> = match metrics {
+            Some(metrics) => metrics,
+            None => Arc::new(NoMetrics),
+        };
+
+        let listener: Arc<ConnectionListener> = match listener {
+            Some(listener) => listener,
+            None => Arc::new(NoListener),
+        };
 
         CONFIG
             .write()
@@ -290,57 +1277,117 @@ impl Connection
                     Config {
                         cluster: cluster,
                         opts: opts,
+                        indexes: OrderMap::new(),
+                        metrics: metrics,
+                        listener: listener,
                         remote: remote,
+                        reactor: None,
+                        server_version: None,
+                        draining: false,
+                        queries: Arc::new(Queries {
+                                              running: Mutex::new(OrderMap::new()),
+                                              done: Condvar::new(),
+                                          }),
                         logger: logger,
                     });
 
         Ok(())
     }
 
+    fn build_pool(&self, logger: &Logger) -> Result<()>
+    {
+        self.set_latency()?;
+        let config = r2d2::Config::builder()
+            .pool_size(144)
+            .idle_timeout(Some(Duration::from_secs(120)))
+            .max_lifetime(Some(Duration::from_secs(86400)))
+            .min_idle(Some(MIN_IDLE))
+            .connection_timeout(self.config().opts.checkout_timeout)
+            .build();
+        let session = SessionManager(*self);
+        let r2d2 = r2d2::Pool::new(config, session)
+            .map_err(|err| io_error(err))?;
+        if self.config().opts.eager {
+            warm_up(&r2d2, logger)?;
+        }
+        self.set_pool(r2d2);
+        Ok(())
+    }
+
     fn maintain(&self)
     {
         self.reset_cluster();
         let conn = *self;
+        let logger = self.config().logger.new(o!("command" => "maintain"));
         let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
         thread::spawn(move || {
-                          let r = Client::new();
+                          let r = Client::new().with_logger(logger.clone());
                           let query = r.db("rethinkdb")
                               .table("server_status")
                               .changes()
                               .with_args(args!({include_initial: true}));
+                          // Whether we lost contact with every server
+                          let mut all_down = false;
                           loop {
-                              let changes = query
-                                  .run::<Change<ServerStatus, ServerStatus>>(conn)
-                                  .unwrap();
+                              // Not registered since it only stops with the connection
+                              let changes = run(&query, conn.into_arg(), None, false);
+                              let changes: Response<Change<ServerStatus, ServerStatus>> = match changes {
+                                  Ok(changes) => changes,
+                                  // The connection was closed
+                                  Err(_) => return,
+                              };
                               for change in changes.wait() {
                                   match change {
                                       Ok(Some(Document::Expected(change))) => {
-                                          if let Some(ref mut config) =
+                                          let mut up = None;
+                                          let mut down = None;
+                                          let listener = if let Some(ref mut config) =
                         CONFIG.write().get_mut(&conn) {
+                                              let listener = config.listener.clone();
                                               let cluster = &mut config.cluster;
                                               if let Some(status) = change.new_val {
-                                                  let mut addresses = Vec::new();
-                                                  for addr in status.network.canonical_addresses {
-                                                      let socket =
-                                                          SocketAddr::new(addr.host,
-                                                                          status.network.reql_port);
-                                                      addresses.push(socket);
+                                                  let mut server = Server::from_status(status);
+                                                  match cluster.get(&server.name) {
+                                                      Some(old) => server.keep_breaker(old),
+                                                      None => up = Some(server.name.clone()),
                                                   }
-                                                  let mut server = Server::new(&status.name,
-                                                                               addresses);
-                                                  server.set_latency();
                                                   cluster.insert(server.name.to_owned(), server);
                                                   let _ = tx.clone().send(());
                                               } else if let Some(status) = change.old_val {
-                                                  cluster.remove(&status.name);
+                                                  if cluster.remove(&status.name).is_some() {
+                                                      down = Some(status.name);
+                                                  }
                                               }
+                                              listener
+                                          } else {
+                                              // The connection was closed
+                                              return;
+                                          };
+                                          // Outside the lock so the listener
+                                          // can use the connection
+                                          if all_down {
+                                              all_down = false;
+                                              listener.on_recovered();
+                                          }
+                                          if let Some(name) = up {
+                                              listener.on_server_up(&name);
+                                          }
+                                          if let Some(name) = down {
+                                              listener.on_server_down(&name);
                                           }
                                       }
                                       Ok(res) => {
-                        println!("unexpected response from server: {:?}", res);
+                        warn!(logger, "unexpected response from server: {:?}", res);
                     }
                                       Err(error) => {
-                        println!("{:?}", error);
+                        error!(logger, "{:?}", error);
+                        if !all_down && is_connection_error(&error) {
+                            all_down = true;
+                            let listener = CONFIG.read().get(&conn).map(|config| config.listener.clone());
+                            if let Some(listener) = listener {
+                                listener.on_all_down();
+                            }
+                        }
                     }
                                   }
                               }
@@ -351,6 +1398,47 @@ impl Connection
         let _ = rx.wait();
     }
 
+    // Read the servers in the cluster once instead of watching them
+    fn discover(&self, logger: &Logger)
+    {
+        let r = Client::new().with_logger(logger.clone());
+        let statuses = match r.db("rethinkdb").table("server_status").run::<ServerStatus>(*self) {
+            Ok(statuses) => statuses,
+            Err(error) => {
+                warn!(logger, "failed to read the servers in the cluster, using the configured servers only: {:?}", error);
+                return;
+            }
+        };
+        let mut cluster = OrderMap::new();
+        for status in statuses.wait() {
+            match status {
+                Ok(Some(Document::Expected(status))) => {
+                    let server = Server::from_status(status);
+                    cluster.insert(server.name.to_owned(), server);
+                }
+                Ok(res) => {
+                    warn!(logger, "unexpected response from server: {:?}", res);
+                }
+                Err(error) => {
+                    // For example the user is not allowed to read `server_status`
+                    warn!(logger, "failed to read the servers in the cluster, using the configured servers only: {:?}", error);
+                    return;
+                }
+            }
+        }
+        if cluster.is_empty() {
+            return;
+        }
+        if let Some(ref mut config) = CONFIG.write().get_mut(self) {
+            for (name, server) in cluster.iter_mut() {
+                if let Some(old) = config.cluster.get(name) {
+                    server.keep_breaker(old);
+                }
+            }
+            config.cluster = cluster;
+        }
+    }
+
     fn reset_cluster(&self)
     {
         if let Some(ref mut config) = CONFIG.write().get_mut(self) {
@@ -385,6 +1473,23 @@ impl Connection
     }
 }
 
+impl SharedConnection
+{
+    /// Get the underlying connection
+    pub fn connection(&self) -> Connection
+    {
+        (self.0).0
+    }
+}
+
+impl Drop for ConnectionHandle
+{
+    fn drop(&mut self)
+    {
+        self.0.close();
+    }
+}
+
 impl Server
 {
     fn new(host: &str, addresses: Vec<SocketAddr>) -> Server
@@ -393,11 +1498,58 @@ impl Server
             name: host.to_string(),
             addresses: addresses,
             latency: Duration::from_millis(u64::max_value()),
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    // Whether new sessions can be opened to this server. Once the cooldown
+    // is over it's tried again, and a single failure avoids it once more.
+    fn is_available(&self) -> bool
+    {
+        match self.open_until {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+
+    fn record_failure(&mut self)
+    {
+        self.failures += 1;
+        if self.failures >= BREAKER_THRESHOLD {
+            self.open_until = Some(Instant::now() + Duration::from_secs(BREAKER_COOLDOWN));
+        }
+    }
+
+    fn record_success(&mut self)
+    {
+        self.failures = 0;
+        self.open_until = None;
+    }
+
+    // Carry the breaker over from the entry this one replaces, since a
+    // change in a server's status says nothing about the queries it fails
+    fn keep_breaker(&mut self, old: &Server)
+    {
+        self.failures = old.failures;
+        self.open_until = old.open_until;
+    }
+
+    fn from_status(status: ServerStatus) -> Server
+    {
+        let mut addresses = Vec::new();
+        for addr in status.network.canonical_addresses {
+            addresses.push(SocketAddr::new(addr.host, status.network.reql_port));
         }
+        let mut server = Server::new(&status.name, addresses);
+        server.set_latency();
+        server
     }
 
     fn set_latency(&mut self)
     {
+        // Assume the server is unreachable until we manage to connect to it
+        self.latency = Duration::from_millis(u64::max_value());
         for address in self.addresses.iter() {
             let start = Instant::now();
             if let Ok(_) = TcpStream::connect(address) {
@@ -406,12 +1558,30 @@ impl Server
             }
         }
     }
+
+    fn is_reachable(&self) -> bool
+    {
+        self.latency < Duration::from_millis(u64::max_value())
+    }
+
+    fn view(&self) -> ServerView
+    {
+        ServerView {
+            name: self.name.clone(),
+            addresses: self.addresses.clone(),
+            latency: self.latency,
+            reachable: self.is_reachable(),
+        }
+    }
 }
 
-fn write_query(conn: &mut Session, query: &str) -> Result<()>
+fn write_query(conn: &mut Session, token: u64, query: &str) -> Result<()>
 {
     let query = query.as_bytes();
-    let token = conn.id;
+    if query.len() > MAX_QUERY_SIZE {
+        let msg = format!("query too large: {} bytes", query.len());
+        return Err(DriverError::Other(msg))?;
+    }
     if let Err(error) = conn.stream.write_u64::<LittleEndian>(token) {
         conn.broken = true;
         return Err(io_error(error))?;
@@ -431,28 +1601,71 @@ fn write_query(conn: &mut Session, query: &str) -> Result<()>
     Ok(())
 }
 
-fn read_query(conn: &mut Session) -> Result<Vec<u8>>
+// Read the response to the query with the given token
+fn read_query(conn: &mut Session, token: u64) -> Result<Vec<u8>>
 {
-    let _ = match conn.stream.read_u64::<LittleEndian>() {
-        Ok(token) => token,
-        Err(error) => {
-            conn.broken = true;
-            return Err(io_error(error))?;
+    loop {
+        let (resp_token, resp) = read_response(conn)?;
+        if resp_token == token {
+            return Ok(resp);
         }
-    };
-    let len = match conn.stream.read_u32::<LittleEndian>() {
-        Ok(len) => len,
-        Err(error) => {
-            conn.broken = true;
-            return Err(io_error(error))?;
+        // Like a late response to a query we already stopped
+        warn!(conn.logger, "ignoring response with token {}, expected {}", resp_token, token);
+    }
+}
+
+// Read a response along with the token of the query it belongs to
+// The token and the length of the response
+const HEADER_SIZE: usize = 12;
+
+// Whatever is read of a response is kept in the session until the whole
+// response is in. So if reading times out, the next call to this function
+// picks up where this one left off.
+fn read_response(conn: &mut Session) -> Result<(u64, Vec<u8>)>
+{
+    fill_pending(conn, HEADER_SIZE)?;
+    let len = LittleEndian::read_u32(&conn.pending[8..HEADER_SIZE]) as usize;
+    fill_pending(conn, HEADER_SIZE + len)?;
+    let token = LittleEndian::read_u64(&conn.pending[..8]);
+    let resp = conn.pending.split_off(HEADER_SIZE);
+    conn.pending.clear();
+    Ok((token, resp))
+}
+
+// Read from the session until `size` bytes of the response are in
+fn fill_pending(conn: &mut Session, size: usize) -> Result<()>
+{
+    let mut buf = [0u8; 8192];
+    while conn.pending.len() < size {
+        let want = cmp::min(size - conn.pending.len(), buf.len());
+        match conn.stream.read(&mut buf[..want]) {
+            Ok(0) => {
+                conn.broken = true;
+                let error = io::Error::from(io::ErrorKind::UnexpectedEof);
+                return Err(read_error(error));
+            }
+            Ok(read) => conn.pending.extend_from_slice(&buf[..read]),
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => {
+                conn.broken = true;
+                if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut {
+                    let msg = String::from("timed out waiting for a response from the server");
+                    return Err(DriverError::Timeout(msg))?;
+                }
+                return Err(read_error(error));
+            }
         }
-    };
-    let mut resp = vec![0u8; len as usize];
-    if let Err(error) = conn.stream.read_exact(&mut resp) {
-        conn.broken = true;
-        return Err(io_error(error))?;
     }
-    Ok(resp)
+    Ok(())
+}
+
+fn read_error(error: io::Error) -> Error
+{
+    if error.kind() == io::ErrorKind::UnexpectedEof {
+        let msg = String::from("the server closed the session before sending a full response");
+        return DriverError::ServerClosed(msg).into();
+    }
+    io_error(error).into()
 }
 
 fn wrap_query(query_type: QueryType, query: Option<String>, options: Option<String>) -> String
@@ -467,3 +1680,537 @@ fn wrap_query(query_type: QueryType, query: Option<String>, options: Option<Stri
     qry.push_str("]");
     qry
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::Discard;
+    use std::net::TcpListener;
+
+    fn session_pair() -> (Session, TcpStream)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let session = Session {
+            token: 0,
+            server: String::from("test"),
+            broken: false,
+            stream: stream,
+            pending: Vec::new(),
+            logger: Logger::root(Discard, o!()),
+        };
+        (session, server)
+    }
+
+    #[test]
+    fn read_response_resumes_after_a_timeout()
+    {
+        let (mut session, mut server) = session_pair();
+        let mut frame = Vec::new();
+        frame.write_u64::<LittleEndian>(7).unwrap();
+        frame.write_u32::<LittleEndian>(5).unwrap();
+        frame.extend_from_slice(b"hello");
+
+        session.stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        server.write_all(&frame[..10]).unwrap();
+        match read_response(&mut session) {
+            Err(Error::Driver(ref error)) => {
+                match **error {
+                    DriverError::Timeout(_) => {}
+                    ref error => panic!("expected a timeout, got {}", error),
+                }
+            }
+            Err(error) => panic!("expected a timeout, got {}", error),
+            Ok((token, _)) => panic!("expected a timeout, got a response with token {}", token),
+        }
+        assert_eq!(session.pending.len(), 10);
+
+        server.write_all(&frame[10..]).unwrap();
+        let (token, resp) = read_response(&mut session).unwrap();
+        assert_eq!(token, 7);
+        assert_eq!(resp, b"hello".to_vec());
+        assert!(session.pending.is_empty());
+    }
+
+    fn safe_mode() -> Opts
+    {
+        let mut opts = Opts::default();
+        opts.safe_mode = true;
+        opts
+    }
+
+    fn run_opts(arg: Arg) -> RunOpts
+    {
+        take_run_opts(&mut arg.term.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn safe_mode_refuses_table_wide_writes()
+    {
+        let r = Client::new();
+        let opts = run_opts(args!());
+        let writes = vec![r.table("users").delete(),
+                          r.branch(args!(true, r.table("users").delete(), 0)),
+                          r.table("logs").for_each(r.table("users").delete()),
+                          r.expr(1).do_(r.table("users").update(args!({active: false})))];
+        for write in writes {
+            let term = write.term.unwrap();
+            assert!(check_safe_mode(&safe_mode(), &opts, &term).is_err());
+            assert!(check_safe_mode(&Opts::default(), &opts, &term).is_ok());
+        }
+        let filtered = r.table("users").filter(args!({active: false})).delete();
+        assert!(check_safe_mode(&safe_mode(), &opts, &filtered.term.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn safe_mode_runs_allowed_table_wide_writes()
+    {
+        let r = Client::new();
+        let opts = run_opts(args!({allow_full_table_write: true}));
+        let term = r.expr(1).do_(r.table("users").delete()).term.unwrap();
+        assert!(check_safe_mode(&safe_mode(), &opts, &term).is_ok());
+    }
+
+    #[test]
+    fn write_query_refuses_queries_too_large_for_the_server()
+    {
+        let (mut session, mut server) = session_pair();
+        let query = "a".repeat(MAX_QUERY_SIZE + 1);
+        let error = write_query(&mut session, 1, &query).unwrap_err();
+        assert!(format!("{:?}", error).contains(&format!("query too large: {} bytes", MAX_QUERY_SIZE + 1)));
+        // Nothing was written so the session can still be used
+        assert!(!session.broken);
+        write_query(&mut session, 2, "[1]").unwrap();
+        let mut frame = [0u8; 15];
+        server.read_exact(&mut frame).unwrap();
+        assert_eq!(LittleEndian::read_u64(&frame[..8]), 2);
+        assert_eq!(LittleEndian::read_u32(&frame[8..12]), 3);
+        assert_eq!(&frame[12..], b"[1]");
+    }
+
+    struct Sessions;
+
+    impl r2d2::ManageConnection for Sessions
+    {
+        type Connection = ();
+        type Error = Error;
+
+        fn connect(&self) -> Result<()>
+        {
+            Ok(())
+        }
+
+        fn is_valid(&self, _: &mut ()) -> Result<()>
+        {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut ()) -> bool
+        {
+            false
+        }
+    }
+
+    #[test]
+    fn checkout_gives_up_on_a_saturated_pool_after_its_timeout()
+    {
+        let config = r2d2::Config::builder().pool_size(1).build();
+        let pool = r2d2::Pool::new(config, Sessions).unwrap();
+        let busy = checkout(&pool, None).unwrap();
+        let start = Instant::now();
+        match checkout(&pool, Some(Duration::from_millis(50))) {
+            Err(Error::Driver(ref error)) => {
+                match **error {
+                    DriverError::PoolExhausted(_) => {}
+                    ref error => panic!("expected the pool to be exhausted, got {}", error),
+                }
+            }
+            Err(error) => panic!("expected the pool to be exhausted, got {}", error),
+            Ok(_) => panic!("checked out a session from a saturated pool"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        drop(busy);
+        assert!(checkout(&pool, Some(Duration::from_millis(50))).is_ok());
+    }
+
+    #[test]
+    fn r_row_arguments_are_sent_as_functions()
+    {
+        let r = Client::new();
+        let predicate = r.row().get_field("age").gt(18);
+        let mut term = r.table("users").filter(predicate.clone()).term.unwrap();
+        wrap_implicit_var(&mut term, false).unwrap();
+        let func = &term.get_args()[1];
+        assert_eq!(func.get_field_type(), TermType::FUNC);
+        assert_eq!(func.get_args()[0].get_datum().get_r_array().len(), 1);
+        assert_eq!(func.get_args()[1], predicate.term.unwrap());
+        // The closure form has the same shape
+        let closure = r.table("users").filter(args!(|user| user.get_field("age").gt(18))).term.unwrap();
+        let closure = &closure.get_args()[1];
+        assert_eq!(closure.get_field_type(), TermType::FUNC);
+        assert_eq!(closure.get_args()[0].get_datum().get_r_array().len(), 1);
+    }
+
+    #[test]
+    fn r_row_is_refused_in_nested_queries()
+    {
+        let r = Client::new();
+        let admin = r.table("admins").filter(r.row().get_field("active")).nth(0);
+        let mut term = r.table("users")
+            .filter(r.row().get_field("id").eq(admin.get_field("id")))
+            .term
+            .unwrap();
+        assert!(wrap_implicit_var(&mut term, false).is_err());
+    }
+
+    #[test]
+    fn session_queries_stay_registered_while_the_session_is_in_use()
+    {
+        let queries = Arc::new(Queries {
+                                   running: Mutex::new(OrderMap::new()),
+                                   done: Condvar::new(),
+                               });
+        let (mut session, _server) = session_pair();
+        let registered = SessionQueries::new(queries.clone(), &session);
+        registered.write(&mut session, 1, "[1,[1]]").unwrap();
+        registered.write(&mut session, 2, "[1,[1]]").unwrap();
+        registered.finish(1);
+        {
+            let running = queries.running.lock();
+            let tokens = running.values().next().unwrap().lock();
+            assert_eq!(tokens.as_ref().unwrap().0, vec![2]);
+        }
+        drop(registered);
+        assert!(queries.running.lock().is_empty());
+    }
+
+    fn server(name: &str, latency: u64) -> Server
+    {
+        let mut server = Server::new(name, Vec::new());
+        server.latency = Duration::from_millis(latency);
+        server
+    }
+
+    fn names(cluster: &OrderMap<String, Server>) -> Vec<&str>
+    {
+        pool::candidates(cluster).into_iter().map(|server| server.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sessions_move_away_from_a_failing_server_until_its_cooldown_is_over()
+    {
+        let mut cluster = OrderMap::new();
+        cluster.insert(String::from("fast"), server("fast", 1));
+        cluster.insert(String::from("slow"), server("slow", 5));
+        assert_eq!(names(&cluster), vec!["fast", "slow"]);
+        for _ in 0..BREAKER_THRESHOLD {
+            cluster.get_mut("fast").unwrap().record_failure();
+        }
+        assert_eq!(names(&cluster), vec!["slow"]);
+        // A change in its status doesn't close the breaker
+        let mut update = server("fast", 1);
+        update.keep_breaker(&cluster["fast"]);
+        cluster.insert(String::from("fast"), update);
+        assert_eq!(names(&cluster), vec!["slow"]);
+        // Once the cooldown is over it's tried again
+        cluster.get_mut("fast").unwrap().open_until = Some(Instant::now());
+        assert_eq!(names(&cluster), vec!["fast", "slow"]);
+        // and a single failure avoids it once more
+        cluster.get_mut("fast").unwrap().record_failure();
+        assert_eq!(names(&cluster), vec!["slow"]);
+        cluster.get_mut("fast").unwrap().record_success();
+        assert_eq!(names(&cluster), vec!["fast", "slow"]);
+    }
+
+    // Set the options of a connection without opening its pool
+    fn configure(arg: Arg) -> Result<Connection>
+    {
+        let conn = Connection(Uuid::new_v4());
+        let remote = Core::new().unwrap().remote();
+        let addrs = vec!["127.0.0.1:28015".parse().unwrap()];
+        conn.set_config(arg.term?, Some(addrs), remote, None, None, Logger::root(Discard, o!()))?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn query_timeout_must_be_positive()
+    {
+        let conn = configure(args!({query_timeout: 5000})).unwrap();
+        assert_eq!(conn.config().opts.query_timeout, Some(Duration::from_secs(5)));
+        conn.close();
+        assert!(configure(args!({query_timeout: 0})).is_err());
+    }
+
+    #[test]
+    fn created_only_treats_an_existing_db_or_table_as_not_created()
+    {
+        let op_failed = |msg: &str| {
+            let error = RuntimeError::Availability(AvailabilityError::OpFailed(msg.to_string()));
+            Err(Error::Runtime(Arc::new(error)))
+        };
+        assert_eq!(created(Ok(None)).unwrap(), true);
+        assert_eq!(created(op_failed("Database `blog` already exists.")).unwrap(), false);
+        assert!(created(op_failed("Cannot perform write: primary replica unavailable")).is_err());
+        let driver = Error::Driver(Arc::new(DriverError::Other("table already exists".into())));
+        assert!(created(Err(driver)).is_err());
+    }
+
+    #[test]
+    fn cluster_views_tell_reachable_servers_apart()
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut up = Server::new("up", vec![closed, listener.local_addr().unwrap()]);
+        let mut down = Server::new("down", vec![closed]);
+        up.set_latency();
+        down.set_latency();
+        let view = up.view();
+        assert_eq!(view.name, "up");
+        assert_eq!(view.addresses.len(), 2);
+        assert!(view.reachable);
+        assert!(view.latency < Duration::from_secs(1));
+        assert!(!down.view().reachable);
+    }
+
+    #[test]
+    fn reproducible_queries_name_their_uuids_after_the_query()
+    {
+        let r = Client::new();
+        let cmd = r.table("users").insert(args!({id: r.uuid(), token: r.uuid()}));
+        let reproducible = || {
+            let mut term = cmd.term.clone().unwrap();
+            make_reproducible(&mut term, &cmd.query, &mut 0).unwrap();
+            term.encode()
+        };
+        let encoded = reproducible();
+        assert_eq!(encoded, reproducible());
+        // Each call gets a name of its own
+        let expected = |n| Value::String(format!("{}#{}", cmd.query, n)).to_string();
+        assert!(encoded.contains(&expected(1)), "{}", encoded);
+        assert!(encoded.contains(&expected(2)), "{}", encoded);
+        assert!(::serde_json::from_str::<Value>(&encoded).is_ok());
+        assert!(make_reproducible(&mut r.now().term.unwrap(), "r.now()", &mut 0).is_err());
+        assert!(make_reproducible(&mut r.random().term.unwrap(), "r.random()", &mut 0).is_err());
+    }
+
+    #[test]
+    fn buffer_size_is_kept_by_the_driver()
+    {
+        let mut term = args!({buffer_size: 4, read_mode: "outdated"}).into_arg().term.unwrap();
+        let opts = take_run_opts(&mut term).unwrap();
+        assert_eq!(opts.buffer_size, 4);
+        let left: Vec<_> = term.get_optargs().iter().map(|pair| pair.get_key()).collect();
+        assert_eq!(left, vec!["read_mode"]);
+        assert_eq!(run_opts(args!()).buffer_size, CHANNEL_SIZE);
+        let mut negative = args!({buffer_size: -1}).into_arg().term.unwrap();
+        assert!(take_run_opts(&mut negative).is_err());
+    }
+
+    fn driver_error(res: Result<Response<Value>>) -> Arc<DriverError>
+    {
+        match res {
+            Err(Error::Driver(error)) => error,
+            Err(error) => panic!("expected a driver error, got {}", error),
+            Ok(_) => panic!("expected a driver error, the query ran"),
+        }
+    }
+
+    #[test]
+    fn running_without_an_open_connection_says_why()
+    {
+        let r = Client::new();
+        match *driver_error(r.expr(1).run(args!({db: "heroes"}))) {
+            DriverError::MissingConnection(ref msg) => assert_eq!(msg, "`run` requires a connection"),
+            ref error => panic!("expected a missing connection, got {:?}", error),
+        }
+        let closed = Connection(Uuid::new_v4());
+        match *driver_error(r.expr(1).run(closed)) {
+            DriverError::ConnectionClosed(_) => {}
+            ref error => panic!("expected a closed connection, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn eager_connect_fails_when_no_server_can_be_reached()
+    {
+        // Nothing listens on the port once the listener is dropped
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let server = format!("127.0.0.1:{}", port);
+        let r = Client::new();
+        let start = Instant::now();
+        let res = r.connect_sync(args!({
+            servers: [server.as_str()],
+            discover_hosts: false,
+            eager: true,
+            checkout_timeout: 200
+        }));
+        assert!(res.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn a_pinned_certificate_is_refused_rather_than_ignored()
+    {
+        let r = Client::new();
+        let res = r.connect_sync(args!({tls: {fingerprint: "5E:FF:56:A2:AF:15:88:25"}, discover_hosts: false}));
+        match res {
+            Err(Error::Driver(ref error)) => {
+                match **error {
+                    DriverError::Tls(_) => {}
+                    ref error => panic!("expected a TLS error, got {}", error),
+                }
+            }
+            Err(error) => panic!("expected a TLS error, got {}", error),
+            Ok(_) => panic!("connected without the pinned certificate"),
+        }
+    }
+
+    #[test]
+    fn a_session_closed_mid_response_is_told_apart_from_other_errors()
+    {
+        let (mut session, mut server) = session_pair();
+        let mut frame = Vec::new();
+        frame.write_u64::<LittleEndian>(3).unwrap();
+        frame.write_u32::<LittleEndian>(10).unwrap();
+        server.write_all(&frame).unwrap();
+        drop(server);
+        let error = read_response(&mut session).unwrap_err();
+        assert!(is_connection_error(&error));
+        match error {
+            Error::Driver(ref error) => {
+                match **error {
+                    DriverError::ServerClosed(_) => {}
+                    ref error => panic!("expected the server to close the session, got {}", error),
+                }
+            }
+            error => panic!("expected the server to close the session, got {}", error),
+        }
+    }
+
+    #[test]
+    fn servers_are_resolved_with_the_default_port()
+    {
+        let hosts = vec![String::from("127.0.0.1"), String::from("127.0.0.1:28016")];
+        let cluster = resolve_cluster(&hosts).unwrap();
+        let ports: Vec<_> = cluster.values().map(|server| server.addresses[0].port()).collect();
+        assert_eq!(ports, vec![28015, 28016]);
+        assert_eq!(cluster.keys().collect::<Vec<_>>(), vec!["127.0.0.1", "127.0.0.1:28016"]);
+    }
+
+    // The database the optional arguments of a query run against
+    fn db_opt(term: &Term) -> String
+    {
+        let pair = term.get_optargs().iter().find(|pair| pair.get_key() == "db").unwrap();
+        let mut names = find_datum(pair.get_val().clone());
+        names.pop().unwrap().take_r_str()
+    }
+
+    #[test]
+    fn the_db_given_to_run_takes_precedence_over_the_connection_default()
+    {
+        let mut opts = Opts::default();
+        opts.db = String::from("connected");
+
+        let mut term = args!().into_arg().term.unwrap();
+        assert_eq!(set_default_opts(&mut term, &opts).unwrap(), "connected");
+        assert_eq!(db_opt(&term), "connected");
+
+        let mut term = args!({db: "ran"}).into_arg().term.unwrap();
+        assert_eq!(set_default_opts(&mut term, &opts).unwrap(), "ran");
+        assert_eq!(term.get_optargs().len(), 1);
+        assert_eq!(db_opt(&term), "ran");
+
+        let r = Client::new();
+        let mut term = args!({db: r.db("chosen")}).into_arg().term.unwrap();
+        assert_eq!(set_default_opts(&mut term, &opts).unwrap(), "chosen");
+        assert_eq!(term.get_optargs().len(), 1);
+    }
+
+    fn frame(token: u64, body: &[u8]) -> Vec<u8>
+    {
+        let mut frame = Vec::new();
+        frame.write_u64::<LittleEndian>(token).unwrap();
+        frame.write_u32::<LittleEndian>(body.len() as u32).unwrap();
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn pipelined_queries_get_the_responses_with_their_tokens()
+    {
+        let (mut session, mut server) = session_pair();
+        let first = session.next_token();
+        let second = session.next_token();
+        assert!(first != second);
+        write_query(&mut session, first, "[1]").unwrap();
+        write_query(&mut session, second, "[2]").unwrap();
+        let mut written = [0u8; 30];
+        server.read_exact(&mut written).unwrap();
+        assert_eq!(&written[..], &[frame(first, b"[1]"), frame(second, b"[2]")].concat()[..]);
+
+        // The second query finishes first
+        server.write_all(&frame(second, b"two")).unwrap();
+        server.write_all(&frame(first, b"one")).unwrap();
+        assert_eq!(read_response(&mut session).unwrap(), (second, b"two".to_vec()));
+        assert_eq!(read_response(&mut session).unwrap(), (first, b"one".to_vec()));
+
+        // A query waiting on its own token skips the responses of the others
+        server.write_all(&frame(second, b"late")).unwrap();
+        server.write_all(&frame(first, b"mine")).unwrap();
+        assert_eq!(read_query(&mut session, first).unwrap(), b"mine".to_vec());
+    }
+
+    #[test]
+    fn an_infinite_range_needs_a_bound()
+    {
+        let r = Client::new();
+        assert!(check_bounded(&r.range().term.unwrap()).is_err());
+        assert!(check_bounded(&r.range().map(args!(|n| n.mul(2))).term.unwrap()).is_err());
+        assert!(check_bounded(&r.range().limit(3).term.unwrap()).is_ok());
+        assert!(check_bounded(&r.range().map(args!(|n| n.mul(2))).limit(3).term.unwrap()).is_ok());
+        assert!(check_bounded(&r.range().with_args(args!(5)).term.unwrap()).is_ok());
+        assert!(check_bounded(&r.range().with_args(args!(2, 5)).term.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn array_limit_is_checked_and_passed_on_to_the_server()
+    {
+        let mut term = args!({array_limit: 300000}).into_arg().term.unwrap();
+        take_run_opts(&mut term).unwrap();
+        let left: Vec<_> = term.get_optargs().iter().map(|pair| pair.get_key()).collect();
+        assert_eq!(left, vec!["array_limit"]);
+        for limit in &[0.0, -5.0, 1.5] {
+            let mut term = args!({array_limit: *limit}).into_arg().term.unwrap();
+            assert!(take_run_opts(&mut term).is_err(), "{}", limit);
+        }
+    }
+
+    #[test]
+    fn default_run_opts_apply_unless_the_run_sets_them()
+    {
+        let r = Client::new().with_default_run_opts(args!({read_mode: "outdated"}));
+        let read_mode = |term: &Term| {
+            let pair = term.get_optargs().iter().find(|pair| pair.get_key() == "read_mode").unwrap();
+            find_datum(pair.get_val().clone()).pop().unwrap().take_r_str()
+        };
+
+        let mut term = args!({durability: "soft"}).into_arg().term.unwrap();
+        merge_run_opts(&mut term, &r.run_opts).unwrap();
+        assert_eq!(read_mode(&term), "outdated");
+        assert_eq!(term.get_optargs().len(), 2);
+
+        let mut term = args!({read_mode: "majority"}).into_arg().term.unwrap();
+        merge_run_opts(&mut term, &r.run_opts).unwrap();
+        assert_eq!(read_mode(&term), "majority");
+        assert_eq!(term.get_optargs().len(), 1);
+    }
+
+    #[test]
+    fn safe_mode_runs_deletes_of_an_index_range()
+    {
+        let r = Client::new();
+        let range = r.table("events").between(args!(r.minval(), 1500000000, {index: "ts"})).delete();
+        assert!(check_safe_mode(&safe_mode(), &run_opts(args!()), &range.term.unwrap()).is_ok());
+    }
+}
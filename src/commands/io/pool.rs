@@ -1,7 +1,10 @@
-use super::io_error;
-use {Connection, Result, Session, SessionManager};
+use super::{CONFIG, io_error};
+use {Connection, Opts, Result, Server, Session, SessionManager};
 use errors::Error;
+use net2::TcpStreamExt;
+use ordermap::OrderMap;
 use r2d2;
+use std::io;
 use std::net::TcpStream;
 
 impl r2d2::ManageConnection for SessionManager
@@ -32,8 +35,7 @@ impl Session
         let cfg = conn.config();
         let logger = cfg.logger;
         //let remote = cfg.remote;
-        let mut servers: Vec<_> = cfg.cluster.values().collect();
-        servers.sort();
+        let servers = candidates(&cfg.cluster);
         debug!(logger, "cluster: {:?}", servers);
 
         for server in servers {
@@ -41,21 +43,28 @@ impl Session
                 debug!(logger, "connecting to {}", address);
                 match TcpStream::connect(&address) {
                     Ok(stream) => {
+                        set_socket_opts(&stream, &cfg.opts)?;
                         let logger = logger.new(o!(
                             "local_addr" => stream.local_addr()?.to_string(),
                             "peer_addr" => format!("{}:{}", server.name, address.port()),
                         ));
 
-                        let mut conn = Session {
-                            id: 0,
+                        let mut session = Session {
+                            token: 0,
+                            server: server.name.clone(),
                             broken: false,
                             stream: stream,
+                            pending: Vec::new(),
                             logger: logger,
                         };
 
-                        conn.handshake(&cfg.opts)?;
-                        debug!(conn.logger, "connected successfully");
-                        return Ok(conn);
+                        let version = session.handshake(&cfg.opts)?;
+                        debug!(session.logger, "connected successfully to RethinkDB {}", version);
+                        if let Some(config) = CONFIG.write().get_mut(&conn) {
+                            config.server_version = Some(version);
+                        }
+                        cfg.metrics.session_created();
+                        return Ok(session);
                     }
                     Err(error) => {
                         warn!(logger, "failed to connect to {}: {}", address, error);
@@ -70,4 +79,56 @@ impl Session
 
         Err(io_error("failed to connect to any server"))?
     }
+
+    /// Get a token for a new query on this session
+    ///
+    /// Tokens only need to be unique among the queries running on the
+    /// same session.
+    pub fn next_token(&mut self) -> u64
+    {
+        self.token = self.token.wrapping_add(1);
+        self.token
+    }
+}
+
+fn set_socket_opts(stream: &TcpStream, opts: &Opts) -> io::Result<()>
+{
+    // Queries are small and we wait for each response
+    // so don't let Nagle's algorithm hold them back
+    stream.set_nodelay(true)?;
+    stream.set_keepalive(opts.keepalive)
+}
+
+// The servers to try opening a session to, the fastest first
+pub fn candidates(cluster: &OrderMap<String, Server>) -> Vec<&Server>
+{
+    let mut servers: Vec<_> = cluster.values().collect();
+    servers.sort();
+    // Stay away from servers that keep failing queries, unless they all do
+    if servers.iter().any(|server| server.is_available()) {
+        servers.retain(|server| server.is_available());
+    }
+    servers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn sessions_skip_nagle_and_keep_alive()
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut opts = Opts::default();
+        opts.keepalive = Some(Duration::from_secs(30));
+        set_socket_opts(&stream, &opts).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert_eq!(stream.keepalive().unwrap(), Some(Duration::from_secs(30)));
+        opts.keepalive = None;
+        set_socket_opts(&stream, &opts).unwrap();
+        assert_eq!(stream.keepalive().unwrap(), None);
+    }
 }
@@ -1,5 +1,5 @@
-use super::{read_query, wrap_query, write_query};
-use {Document, ReqlResponse, Request, Result, Session, SessionManager};
+use super::{CONFIG, checkout, is_connection_error, read_query, read_response, wrap_query, write_query};
+use {Document, Overflow, ReqlResponse, Request, Result, Session, SessionManager};
 
 use errors::*;
 use futures::{Future, Sink};
@@ -11,26 +11,111 @@ use r2d2::PooledConnection;
 use serde::de::DeserializeOwned;
 use serde_json::{Value, from_slice, from_value};
 use std::error::Error as StdError;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use types::Encode;
 
 impl<T: DeserializeOwned + Send> Request<T>
 {
-    fn conn(&self) -> Result<PooledConnection<SessionManager>>
+    fn conn(&mut self) -> Result<PooledConnection<SessionManager>>
     {
-        match self.pool.get() {
-            Ok(mut conn) => {
-                conn.id = conn.id.wrapping_add(1);
-                Ok(conn)
+        let start = Instant::now();
+        let conn = checkout(&self.pool, self.checkout_timeout);
+        self.cfg.metrics.pool_checkout(start.elapsed());
+        let mut conn = conn?;
+        self.token = conn.next_token();
+        self.server = Some(conn.server.clone());
+        *self.running.lock() = conn.stream.try_clone().ok().map(|stream| (vec![self.token], stream));
+        Ok(conn)
+    }
+
+    pub fn submit(mut self)
+    {
+        let start = Instant::now();
+        self.submit_query();
+        self.cfg.queries.running.lock().remove(&self.id);
+        self.cfg.queries.done.notify_all();
+        if !self.failed {
+            if let Some(name) = self.server.take() {
+                if let Some(config) = CONFIG.write().get_mut(&self.conn) {
+                    if let Some(server) = config.cluster.get_mut(&name) {
+                        server.record_success();
+                    }
+                }
             }
-            Err(error) => Err(error)?,
         }
+        self.cfg.metrics.query_completed(start.elapsed(), !self.failed);
     }
 
-    pub fn submit(mut self)
+    // Count a failure against the server of the current session
+    //
+    // Only once per session, since the query moves on to a new one or fails.
+    fn server_failed(&mut self)
+    {
+        if let Some(name) = self.server.take() {
+            if let Some(config) = CONFIG.write().get_mut(&self.conn) {
+                if let Some(server) = config.cluster.get_mut(&name) {
+                    server.record_failure();
+                }
+            }
+        }
+    }
+
+    fn send_error(&mut self, error: Error)
+    {
+        if is_connection_error(&error) {
+            self.server_failed();
+        }
+        self.failed = true;
+        self.send(Err(error));
+    }
+
+    fn send(&mut self, item: Result<Option<Document<T>>>)
+    {
+        let backlog = match self.backlog {
+            Some(ref backlog) => backlog.clone(),
+            None => {
+                if self.tx.send(item).is_err() {
+                    // The caller dropped the response
+                    self.dropped = true;
+                }
+                return;
+            }
+        };
+        if self.dropped {
+            return;
+        }
+        {
+            let mut docs = backlog.docs.lock();
+            if docs.len() >= backlog.size {
+                if backlog.overflow == Overflow::Error {
+                    let msg = format!("more than {} results are waiting to be consumed", backlog.size);
+                    docs.push_back(Err(DriverError::Other(msg).into()));
+                    // Stops the query
+                    self.dropped = true;
+                } else {
+                    docs.pop_front();
+                    backlog.dropped.fetch_add(1, Ordering::SeqCst);
+                    docs.push_back(item);
+                }
+            } else {
+                docs.push_back(item);
+            }
+        }
+        // If the channel is full a wake up is already on its way
+        if let Err(error) = self.tx.get_mut().try_send(Ok(None)) {
+            if error.is_disconnected() {
+                self.dropped = true;
+            }
+        }
+    }
+
+    fn submit_query(&mut self)
     {
         let mut conn = match self.conn() {
             Ok(conn) => conn,
             Err(error) => {
-                let _ = self.tx.clone().send(Err(error)).wait();
+                self.send_error(error);
                 return;
             }
         };
@@ -51,7 +136,7 @@ impl<T: DeserializeOwned + Send> Request<T>
                         Ok(c) => c,
                         Err(error) => {
                             if i == self.cfg.opts.retries - 1 {
-                                let _ = self.tx.clone().send(Err(error.into())).wait();
+                                self.send_error(error.into());
                                 if !reproducible {
                                     return;
                                 }
@@ -63,13 +148,9 @@ impl<T: DeserializeOwned + Send> Request<T>
                     self.logger = conn.logger.clone();
                     self.write = true;
                 }
-                let commands = {
-                    let term = self.term.clone();
-                    self.encode(&term, false)
-                };
+                let commands = self.term.encode();
                 let opts = {
-                    let term = self.opts.clone();
-                    let res = self.encode(&term, true);
+                    let res = self.opts.encode();
                     if res.is_empty() {
                         None
                     } else {
@@ -82,10 +163,15 @@ impl<T: DeserializeOwned + Send> Request<T>
                 // Submit the query if necessary
                 if self.write || reproducible {
                     debug!(self.logger, "submitting query");
-                    if let Err(error) = write_query(&mut conn, &query) {
+                    let written = {
+                        let _running = self.running.lock();
+                        write_query(&mut conn, self.token, &query)
+                    };
+                    if let Err(error) = written {
+                        self.server_failed();
                         connect = true;
                         if i == self.cfg.opts.retries - 1 {
-                            let _ = self.tx.clone().send(Err(error.into())).wait();
+                            self.send_error(error.into());
                             if !reproducible {
                                 return;
                             }
@@ -99,10 +185,33 @@ impl<T: DeserializeOwned + Send> Request<T>
                         connect = false;
                     }
                 }
+                // Bound how long we are willing to wait for the first response
+                if let Some(deadline) = self.deadline {
+                    let now = Instant::now();
+                    let wait = if deadline > now {
+                        deadline - now
+                    } else {
+                        Duration::from_millis(1)
+                    };
+                    if let Err(error) = conn.stream.set_read_timeout(Some(wait)) {
+                        self.send_error(error.into());
+                        return;
+                    }
+                }
                 // Handle the response
                 if let Err(error) = self.process(&mut conn, &mut query) {
+                    if is_timeout(&error) {
+                        self.stop(&mut conn);
+                        self.send_error(error);
+                        return;
+                    }
+                    if is_server_closed(&error) {
+                        // Resubmit the query on a new session
+                        self.server_failed();
+                        connect = true;
+                    }
                     if i == self.cfg.opts.retries - 1 || !self.retry {
-                        let _ = self.tx.clone().send(Err(error.into())).wait();
+                        self.send_error(error.into());
                         if !reproducible {
                             return;
                         }
@@ -121,10 +230,23 @@ impl<T: DeserializeOwned + Send> Request<T>
         self.write = false;
         match self.handle(conn) {
             Ok(t) => {
+                if self.deadline.take().is_some() {
+                    // We have our first response so the deadline no longer applies
+                    conn.stream.set_read_timeout(None)?;
+                }
                 match t {
                     Some(ResponseType::SUCCESS_PARTIAL) => {
+                        // Nobody is listening so there is no point fetching the rest
+                        if self.dropped {
+                            self.stop(conn);
+                            return Ok(());
+                        }
                         *query = wrap_query(QueryType::CONTINUE, None, None);
-                        if let Err(error) = write_query(conn, query) {
+                        let written = {
+                            let _running = self.running.lock();
+                            write_query(conn, self.token, query)
+                        };
+                        if let Err(error) = written {
                             self.write = true;
                             self.retry = true;
                             return Err(error)?;
@@ -156,82 +278,21 @@ impl<T: DeserializeOwned + Send> Request<T>
     fn handle(&mut self, conn: &mut Session) -> Result<Option<ResponseType>>
     {
         self.retry = false;
-        match read_query(conn) {
+        match self.read(conn) {
             Ok(resp) => {
+                self.received = true;
                 let result: ReqlResponse = from_slice(&resp[..])?;
-                let respt: ResponseType;
-                if let Some(t) = ResponseType::from_i32(result.t) {
-                    respt = t;
-                } else {
-                    let msg = format!("Unsupported response type ({}), returned by the database.", result.t);
-                    return Err(DriverError::Other(msg))?;
-                }
-                // If the database says this response is an error convert the error
-                // message to our native one.
-                let has_generic_error = match respt {
-                    ResponseType::CLIENT_ERROR |
-                    ResponseType::COMPILE_ERROR |
-                    ResponseType::RUNTIME_ERROR => true,
-                    _ => false,
-                };
-                let mut msg = String::new();
-                if result.e.is_some() || has_generic_error {
-                    msg = if let Value::Array(error) = result.r.clone() {
-                        if error.len() == 1 {
-                            if let Some(Value::String(msg)) = error.into_iter().next() {
-                                msg
-                            } else {
-                                return Err(ResponseError::Db(result.r))?;
-                            }
-                        } else {
-                            return Err(ResponseError::Db(result.r))?;
-                        }
-                    } else {
-                        return Err(ResponseError::Db(result.r))?;
-                    };
-                }
-                if let Some(e) = result.e {
-                    if let Some(error) = ErrorType::from_i32(e) {
-                        match error {
-                            ErrorType::INTERNAL => return Err(RuntimeError::Internal(msg))?,
-                            ErrorType::RESOURCE_LIMIT => {
-                                return Err(RuntimeError::ResourceLimit(msg))?
-                            }
-                            ErrorType::QUERY_LOGIC => return Err(RuntimeError::QueryLogic(msg))?,
-                            ErrorType::NON_EXISTENCE => {
-                                return Err(RuntimeError::NonExistence(msg))?
-                            }
-                            ErrorType::OP_FAILED => return Err(AvailabilityError::OpFailed(msg))?,
-                            ErrorType::OP_INDETERMINATE => {
-                                return Err(AvailabilityError::OpIndeterminate(msg))?
-                            }
-                            ErrorType::USER => return Err(RuntimeError::User(msg))?,
-                            ErrorType::PERMISSION_ERROR => {
-                                return Err(RuntimeError::Permission(msg))?
-                            }
-                        }
-                    } else {
-                        return Err(ResponseError::Db(result.r))?;
-                    }
-                }
-                if has_generic_error {
-                    match respt {
-                        ResponseType::CLIENT_ERROR => return Err(DriverError::Other(msg))?,
-                        ResponseType::COMPILE_ERROR => return Err(Error::Compile(msg))?,
-                        ResponseType::RUNTIME_ERROR => return Err(ResponseError::Db(result.r))?,
-                        _ => { /* not an error */ }
-                    }
+                let respt = response_type(&result, &self.query)?;
+                if let Some(ref profile) = result.p {
+                    *self.profile.write() = Some(profile.clone());
                 }
                 // Since this is a successful query let's process the results and send
                 // them to the caller
                 if let Ok(data) = from_value::<T>(result.r.clone()) {
-                    let _ = self.tx
-                        .clone()
-                        .send(Ok(Some(Document::Expected(data))))
-                        .wait();
+                    self.send(Ok(Some(Document::Expected(data))));
                 } else if let Ok(data) = from_value::<Vec<T>>(result.r.clone()) {
                     for v in data {
-                        let _ = self.tx.clone().send(Ok(Some(Document::Expected(v)))).wait();
+                        self.send(Ok(Some(Document::Expected(v))));
                     }
                 }
                 // Send unexpected query responses
@@ -242,26 +303,20 @@ impl<T: DeserializeOwned + Send> Request<T>
                     for v in data {
                         match v {
                             Value::Null => {
-                                let _ = self.tx.clone().send(Ok(None)).wait();
+                                self.send(Ok(None));
                             }
                             value => {
-                                let _ = self.tx
-                                    .clone()
-                                    .send(Ok(Some(Document::Unexpected(value))))
-                                    .wait();
+                                self.send(Ok(Some(Document::Unexpected(value))));
                             }
                         }
                     }
                 } else {
                     match result.r.clone() {
                         Value::Null => {
-                            let _ = self.tx.clone().send(Ok(None)).wait();
+                            self.send(Ok(None));
                         }
                         value => {
-                            let _ = self.tx
-                                .clone()
-                                .send(Ok(Some(Document::Unexpected(value))))
-                                .wait();
+                            self.send(Ok(Some(Document::Unexpected(value))));
                         }
                     }
                 }
@@ -271,9 +326,172 @@ impl<T: DeserializeOwned + Send> Request<T>
             // We failed to read the server's response so we will
             // try again as long as we haven't used up all our allowed retries.
             Err(error) => {
-                self.retry = true;
+                self.retry = if is_server_closed(&error) {
+                    // Submitting the query again would send the caller
+                    // the results it already has a second time
+                    !self.received
+                } else {
+                    !is_timeout(&error)
+                };
                 return Err(error)?;
             }
         }
     }
+
+    // Read the next response, checking on the server if it's quiet for too long
+    //
+    // Only applies once the first response is in, since `deadline` covers that
+    // one. When nothing arrives within `idle_timeout` a `noreply_wait` is sent
+    // on the same session. Any response after that shows the server is still
+    // there but if none arrives either the connection is considered dead.
+    fn read(&mut self, conn: &mut Session) -> Result<Vec<u8>>
+    {
+        let idle = match self.idle_timeout {
+            Some(idle) if self.received => idle,
+            _ => return read_query(conn, self.token),
+        };
+        conn.stream.set_read_timeout(Some(idle))?;
+        let mut pinged = false;
+        loop {
+            match read_response(conn) {
+                Ok((token, resp)) => {
+                    pinged = false;
+                    if token == self.token {
+                        conn.stream.set_read_timeout(None)?;
+                        return Ok(resp);
+                    }
+                    // Like the reply to our check
+                    debug!(self.logger, "ignoring response with token {}, expected {}", token, self.token);
+                }
+                Err(ref error) if is_timeout(error) && !pinged => {
+                    debug!(self.logger, "no results for {:?}, checking the server", idle);
+                    // Any part of a response read so far is kept in the
+                    // session, so it's still in one piece
+                    conn.broken = false;
+                    let token = conn.next_token();
+                    let query = wrap_query(QueryType::NOREPLY_WAIT, None, None);
+                    let written = {
+                        let _running = self.running.lock();
+                        write_query(conn, token, &query)
+                    };
+                    written?;
+                    pinged = true;
+                }
+                Err(error) => {
+                    if is_timeout(&error) {
+                        conn.broken = true;
+                        // Handled like a closed connection so the query isn't retried on it
+                        let msg = format!("the server didn't respond after the query was idle for {:?}", idle);
+                        return Err(DriverError::ServerClosed(msg))?;
+                    }
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self, conn: &mut Session)
+    {
+        debug!(self.logger, "stopping query");
+        let query = wrap_query(QueryType::STOP, None, None);
+        {
+            let _running = self.running.lock();
+            let _ = write_query(conn, self.token, &query);
+        }
+        // The server may still send a response to the original query
+        // so this session can't be reused.
+        conn.broken = true;
+    }
+}
+
+// Get the type of a response, converting any error reported by the
+// database to our native one
+pub fn response_type(result: &ReqlResponse, query: &str) -> Result<ResponseType>
+{
+    let respt: ResponseType;
+    if let Some(t) = ResponseType::from_i32(result.t) {
+        respt = t;
+    } else {
+        let msg = format!("Unsupported response type ({}), returned by the database.", result.t);
+        return Err(DriverError::Other(msg))?;
+    }
+    // If the database says this response is an error convert the error
+    // message to our native one.
+    let has_generic_error = match respt {
+        ResponseType::CLIENT_ERROR |
+        ResponseType::COMPILE_ERROR |
+        ResponseType::RUNTIME_ERROR => true,
+        _ => false,
+    };
+    let mut msg = String::new();
+    if result.e.is_some() || has_generic_error {
+        msg = if let Value::Array(error) = result.r.clone() {
+            if error.len() == 1 {
+                if let Some(Value::String(msg)) = error.into_iter().next() {
+                    msg
+                } else {
+                    return Err(ResponseError::Db(result.r.clone()))?;
+                }
+            } else {
+                return Err(ResponseError::Db(result.r.clone()))?;
+            }
+        } else {
+            return Err(ResponseError::Db(result.r.clone()))?;
+        };
+        // Point the user to the query that caused the error
+        msg = format!("{} in:\n{}", msg, query);
+    }
+    if let Some(e) = result.e {
+        if let Some(error) = ErrorType::from_i32(e) {
+            match error {
+                ErrorType::INTERNAL => return Err(RuntimeError::Internal(msg))?,
+                ErrorType::RESOURCE_LIMIT => {
+                    return Err(RuntimeError::ResourceLimit(msg))?
+                }
+                ErrorType::QUERY_LOGIC => return Err(RuntimeError::QueryLogic(msg))?,
+                ErrorType::NON_EXISTENCE => {
+                    return Err(RuntimeError::NonExistence(msg))?
+                }
+                ErrorType::OP_FAILED => return Err(AvailabilityError::OpFailed(msg))?,
+                ErrorType::OP_INDETERMINATE => {
+                    return Err(AvailabilityError::OpIndeterminate(msg))?
+                }
+                ErrorType::USER => return Err(RuntimeError::User(msg))?,
+                ErrorType::PERMISSION_ERROR => {
+                    return Err(RuntimeError::Permission(msg))?
+                }
+            }
+        } else {
+            return Err(ResponseError::Db(result.r.clone()))?;
+        }
+    }
+    if has_generic_error {
+        match respt {
+            ResponseType::CLIENT_ERROR => return Err(DriverError::Other(msg))?,
+            ResponseType::COMPILE_ERROR => return Err(Error::Compile(msg))?,
+            ResponseType::RUNTIME_ERROR => return Err(ResponseError::Db(result.r.clone()))?,
+            _ => { /* not an error */ }
+        }
+    }
+    Ok(respt)
+}
+
+fn is_server_closed(error: &Error) -> bool
+{
+    if let Error::Driver(ref error) = *error {
+        if let DriverError::ServerClosed(_) = **error {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_timeout(error: &Error) -> bool
+{
+    if let Error::Driver(ref error) = *error {
+        if let DriverError::Timeout(_) = **error {
+            return true;
+        }
+    }
+    false
 }
@@ -0,0 +1,523 @@
+use {Document, FilterOk, MapOk, Response, Result};
+use errors::{DriverError, Error};
+use futures::{Async, Poll, Stream};
+use reql_types::Change;
+use serde::de::DeserializeOwned;
+use serde_json::{from_value, Value};
+use serde_path_to_error;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+
+impl<T: DeserializeOwned + Send> Response<T>
+{
+    /// Wait for a single document
+    ///
+    /// This is meant for queries like `get` that return at most one document.
+    /// A missing document, which the server returns as `null`, comes back as
+    /// `Ok(None)` so there is no need to model it in `T`.
+    pub fn single(self) -> Result<Option<T>>
+    {
+        match self.wait().next() {
+            Some(Ok(Some(doc))) => doc.into_result().map(Some),
+            Some(Ok(None)) | None => Ok(None),
+            Some(Err(error)) => Err(error),
+        }
+    }
+
+    /// Collect at most `max` documents
+    ///
+    /// Returns an error as soon as the query returns more than `max`
+    /// documents, so an accidentally unbounded query can't use up all the
+    /// memory. The rest of the results are not fetched from the server.
+    pub fn collect_capped(self, max: usize) -> Result<Vec<T>>
+    {
+        let mut docs = Vec::new();
+        // Dropping the response before it's done stops the query
+        for doc in self.wait() {
+            if let Some(doc) = doc? {
+                let doc = doc.into_result()?;
+                if docs.len() == max {
+                    let msg = format!("result exceeded {} rows", max);
+                    return Err(DriverError::Other(msg))?;
+                }
+                docs.push(doc);
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Collect all the documents along with the profile of the query
+    ///
+    /// The profile is only returned by the server when the query is run
+    /// with `profile: true`, otherwise it's `None`. Any document that is not
+    /// a `T` is an error, just like with `collect_capped`.
+    pub fn into_vec_with_profile(self) -> Result<(Vec<T>, Option<Value>)>
+    {
+        let profile = self.profile.clone();
+        let mut docs = Vec::new();
+        for doc in self.wait() {
+            if let Some(doc) = doc? {
+                docs.push(doc.into_result()?);
+            }
+        }
+        let profile = profile.read().clone();
+        Ok((docs, profile))
+    }
+
+    /// The number of results discarded because they were not consumed in time
+    ///
+    /// Only the `drop_oldest` overflow policy discards results, so this is
+    /// always `0` for queries run without it.
+    pub fn dropped(&self) -> usize
+    {
+        match self.backlog {
+            Some(ref backlog) => backlog.dropped.load(Ordering::SeqCst),
+            None => 0,
+        }
+    }
+
+    /// Apply a function to each document as it arrives
+    ///
+    /// Only `Document::Expected` values are passed to `f`. Unexpected values
+    /// and errors are passed through untouched, so they can still be
+    /// handled further down the stream.
+    pub fn map_ok<U, F>(self, f: F) -> MapOk<T, F>
+        where U: DeserializeOwned + Send,
+              F: FnMut(T) -> U
+    {
+        MapOk {
+            response: self,
+            f: f,
+        }
+    }
+
+    /// Skip documents that don't match a predicate
+    ///
+    /// Only `Document::Expected` values are passed to `f`. Since there is no
+    /// way to tell whether unexpected values match, they are always kept, as
+    /// are errors.
+    pub fn filter_ok<F>(self, f: F) -> FilterOk<T, F>
+        where F: FnMut(&T) -> bool
+    {
+        FilterOk {
+            response: self,
+            f: f,
+        }
+    }
+}
+
+impl<T, U, F> Stream for MapOk<T, F>
+    where T: DeserializeOwned + Send,
+          U: DeserializeOwned + Send,
+          F: FnMut(T) -> U
+{
+    type Item = Option<Document<U>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error>
+    {
+        let doc = match self.response.poll()? {
+            Async::Ready(Some(Some(doc))) => doc,
+            Async::Ready(Some(None)) => return Ok(Async::Ready(Some(None))),
+            Async::Ready(None) => return Ok(Async::Ready(None)),
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        let doc = match doc {
+            Document::Expected(doc) => Document::Expected((self.f)(doc)),
+            Document::Unexpected(value) => Document::Unexpected(value),
+        };
+        Ok(Async::Ready(Some(Some(doc))))
+    }
+}
+
+impl<T, F> Stream for FilterOk<T, F>
+    where T: DeserializeOwned + Send,
+          F: FnMut(&T) -> bool
+{
+    type Item = Option<Document<T>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error>
+    {
+        loop {
+            match self.response.poll()? {
+                Async::Ready(Some(Some(Document::Expected(doc)))) => {
+                    if (self.f)(&doc) {
+                        return Ok(Async::Ready(Some(Some(Document::Expected(doc)))));
+                    }
+                }
+                res => return Ok(res),
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Send> Response<Change<T, T>>
+{
+    /// Wait for the initial values of a changefeed and return them with the rest of it
+    ///
+    /// The feed must be opened with both `include_initial` and `include_states`
+    /// set, so the server sends all the initial values followed by a
+    /// `{state: "ready"}` document before any live change. The initial values
+    /// are collected until then and the response is handed back to follow the
+    /// changes made after them.
+    pub fn split_initial(self) -> Result<(Vec<T>, Response<Change<T, T>>)>
+    {
+        let mut response = self;
+        let mut initial = Vec::new();
+        for doc in (&mut response).wait() {
+            let change = match doc? {
+                Some(doc) => doc.into_result()?,
+                None => continue,
+            };
+            match change.state {
+                Some(ref state) if state == "ready" => break,
+                Some(_) => continue,
+                None => {}
+            }
+            if let Some(val) = change.new_val {
+                initial.push(val);
+            }
+        }
+        Ok((initial, response))
+    }
+}
+
+impl Response<Value>
+{
+    /// Collect a grouped result or a key/value sequence into a `HashMap`
+    ///
+    /// The server returns grouped data, like the result of
+    /// `group("player").count()`, as a `GROUPED_DATA` pseudotype holding a
+    /// list of `[group, reduction]` pairs. Each group becomes a key of the map
+    /// and its reduction the value. The same goes for results that have been
+    /// ungrouped into `{group, reduction}` objects, sequences of `[key, value]`
+    /// arrays and objects, such as those returned by `coerce_to("object")`.
+    pub fn into_map<K, V>(self) -> Result<HashMap<K, V>>
+        where K: DeserializeOwned + Eq + Hash,
+              V: DeserializeOwned
+    {
+        let mut map = HashMap::new();
+        for doc in self.wait() {
+            match doc? {
+                Some(Document::Expected(Value::Array(items))) |
+                Some(Document::Unexpected(Value::Array(items))) => {
+                    for item in items {
+                        insert_pairs(&mut map, item)?;
+                    }
+                }
+                Some(Document::Expected(value)) |
+                Some(Document::Unexpected(value)) => {
+                    insert_pairs(&mut map, value)?;
+                }
+                None => {}
+            }
+        }
+        Ok(map)
+    }
+}
+
+fn insert_pairs<K, V>(map: &mut HashMap<K, V>, value: Value) -> Result<()>
+    where K: DeserializeOwned + Eq + Hash,
+          V: DeserializeOwned
+{
+    match value {
+        Value::Array(mut pair) => {
+            if pair.len() != 2 {
+                let msg = format!("expected a [key, value] pair, found {}", Value::Array(pair));
+                return Err(DriverError::Other(msg))?;
+            }
+            let val = pair.pop().unwrap();
+            let key = pair.pop().unwrap();
+            map.insert(from_value(key)?, from_value(val)?);
+        }
+        Value::Object(mut obj) => {
+            let is_grouped = obj.get("$reql_type$") == Some(&Value::String("GROUPED_DATA".into()));
+            if is_grouped {
+                if let Some(Value::Array(data)) = obj.remove("data") {
+                    for pair in data {
+                        insert_pairs(map, pair)?;
+                    }
+                }
+            } else if obj.len() == 2 && obj.contains_key("group") && obj.contains_key("reduction") {
+                let key = obj.remove("group").unwrap();
+                let val = obj.remove("reduction").unwrap();
+                map.insert(from_value(key)?, from_value(val)?);
+            } else {
+                for (key, val) in obj {
+                    map.insert(from_value(Value::String(key))?, from_value(val)?);
+                }
+            }
+        }
+        value => {
+            let msg = format!("expected a key/value pair, found {}", value);
+            return Err(DriverError::Other(msg))?;
+        }
+    }
+    Ok(())
+}
+
+impl<T: DeserializeOwned + Send> Document<T>
+{
+    /// Turn the document into a `Result`
+    ///
+    /// An expected document is returned as it is. For an unexpected one the
+    /// error includes the raw value and why it could not be deserialized
+    /// into `T`.
+    pub fn into_result(self) -> Result<T>
+    {
+        match self {
+            Document::Expected(doc) => Ok(doc),
+            Document::Unexpected(value) => Err(unexpected::<T>(&value)),
+        }
+    }
+
+    /// Whether the document could not be deserialized into `T`
+    pub fn is_unexpected(&self) -> bool
+    {
+        match *self {
+            Document::Unexpected(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The raw value of a document that could not be deserialized into `T`
+    ///
+    /// This is handy for finding out how the documents stored in the
+    /// database differ from the type they are being deserialized into.
+    pub fn unexpected_value(&self) -> Option<&Value>
+    {
+        match *self {
+            Document::Unexpected(ref value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Deserialize a value, reporting the path of the field that doesn't fit `T`
+pub fn deserialize<T: DeserializeOwned>(value: Value) -> Result<T>
+{
+    match from_value(value.clone()) {
+        Ok(data) => Ok(data),
+        Err(_) => Err(unexpected::<T>(&value)),
+    }
+}
+
+// Describe why a document could not be deserialized into `T`
+fn unexpected<T: DeserializeOwned>(value: &Value) -> Error
+{
+    // Unlike `from_value`, this tells us where the error is
+    let reason = match serde_path_to_error::deserialize::<_, T>(value.clone()) {
+        Err(error) => {
+            let path = error.path().to_string();
+            let reason = error.into_inner();
+            // The path of the document itself
+            if path == "." {
+                Some(reason.to_string())
+            } else {
+                Some(format!("{} at `{}`", reason, path))
+            }
+        }
+        Ok(_) => None,
+    };
+    let msg = match reason {
+        Some(reason) => format!("unexpected response from server ({}): {}", reason, value),
+        None => format!("unexpected response from server: {}", value),
+    };
+    Error::from(DriverError::Deserialize(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Sink;
+    use futures::sync::mpsc;
+    use parking_lot::RwLock;
+    use serde_json::from_str;
+    use std::sync::Arc;
+
+    #[derive(Debug, Deserialize)]
+    struct User
+    {
+        name: String,
+        emails: Vec<Email>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Email
+    {
+        address: String,
+        verified: bool,
+    }
+
+    fn message(error: Error) -> String
+    {
+        match error {
+            Error::Driver(ref error) => {
+                match **error {
+                    DriverError::Deserialize(ref msg) => msg.clone(),
+                    ref error => panic!("expected a deserialization error, got {}", error),
+                }
+            }
+            error => panic!("expected a deserialization error, got {}", error),
+        }
+    }
+
+    // A response streaming the given documents, as if they came from the server
+    fn response<T: DeserializeOwned + Send>(docs: Vec<Option<Document<T>>>) -> Response<T>
+    {
+        let (tx, rx) = mpsc::channel(docs.len());
+        let mut tx = tx.wait();
+        for doc in docs {
+            tx.send(Ok(doc)).unwrap();
+        }
+        Response {
+            done: false,
+            rx: rx,
+            profile: Arc::new(RwLock::new(None)),
+            backlog: None,
+        }
+    }
+
+    #[test]
+    fn single_returns_a_missing_document_as_none()
+    {
+        assert_eq!(response(vec![Some(Document::Expected(5u64))]).single().unwrap(), Some(5));
+        assert_eq!(response::<u64>(vec![None]).single().unwrap(), None);
+        assert_eq!(response::<u64>(Vec::new()).single().unwrap(), None);
+        let wrong = Document::Unexpected(Value::String(String::from("five")));
+        assert!(response::<u64>(vec![Some(wrong)]).single().is_err());
+    }
+
+    #[test]
+    fn deserialize_reports_the_path_of_a_nested_mismatch()
+    {
+        let value = from_str(r#"{"name": "bob", "emails": [{"address": "a@b.c", "verified": true},
+                                                           {"address": "d@e.f", "verified": "no"}]}"#)
+            .unwrap();
+        let msg = message(deserialize::<User>(value).unwrap_err());
+        assert!(msg.contains("at `emails[1].verified`"), msg);
+    }
+
+    #[test]
+    fn deserialize_reports_a_mismatch_of_the_whole_document()
+    {
+        let msg = message(deserialize::<User>(Value::Bool(true)).unwrap_err());
+        assert!(!msg.contains(" at `"), msg);
+        assert!(msg.ends_with(": true"), msg);
+    }
+
+    fn map_of(items: &[&str]) -> Result<HashMap<String, u64>>
+    {
+        let mut map = HashMap::new();
+        for item in items {
+            insert_pairs(&mut map, from_str(item).unwrap())?;
+        }
+        Ok(map)
+    }
+
+    #[test]
+    fn into_map_collects_groups_pairs_and_objects()
+    {
+        let mut expected = HashMap::new();
+        expected.insert(String::from("alice"), 3);
+        expected.insert(String::from("bob"), 5);
+        let grouped = r#"{"$reql_type$": "GROUPED_DATA", "data": [["alice", 3], ["bob", 5]]}"#;
+        assert_eq!(map_of(&[grouped]).unwrap(), expected);
+        assert_eq!(map_of(&[r#"["alice", 3]"#, r#"["bob", 5]"#]).unwrap(), expected);
+        let ungrouped = [r#"{"group": "alice", "reduction": 3}"#, r#"{"group": "bob", "reduction": 5}"#];
+        assert_eq!(map_of(&ungrouped).unwrap(), expected);
+        assert_eq!(map_of(&[r#"{"alice": 3, "bob": 5}"#]).unwrap(), expected);
+    }
+
+    #[test]
+    fn into_map_rejects_values_that_are_not_pairs()
+    {
+        assert!(map_of(&[r#"["alice", 3, 5]"#]).is_err());
+        assert!(map_of(&["3"]).is_err());
+    }
+
+    #[test]
+    fn into_result_returns_an_expected_document()
+    {
+        let doc = Document::Expected(5u64);
+        assert!(!doc.is_unexpected());
+        assert_eq!(doc.into_result().unwrap(), 5);
+    }
+
+    #[test]
+    fn into_result_includes_the_raw_value_of_an_unexpected_document()
+    {
+        let value: Value = from_str(r#"{"name": "bob", "emails": "bob@example.com"}"#).unwrap();
+        let doc: Document<User> = Document::Unexpected(value.clone());
+        assert!(doc.is_unexpected());
+        assert_eq!(doc.unexpected_value(), Some(&value));
+        let msg = message(doc.into_result().unwrap_err());
+        assert!(msg.contains("at `emails`"), msg);
+        assert!(msg.ends_with(&value.to_string()), msg);
+    }
+
+    #[test]
+    fn collect_capped_refuses_more_rows_than_the_cap()
+    {
+        let rows = || (0..100u64).map(|n| Some(Document::Expected(n))).collect::<Vec<_>>();
+        let error = response(rows()).collect_capped(10).unwrap_err();
+        assert!(format!("{}", error).contains("result exceeded 10 rows"), "{}", error);
+        assert_eq!(response(rows()).collect_capped(100).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn filter_ok_only_tests_the_expected_documents()
+    {
+        let text = Value::String(String::from("three"));
+        let docs = vec![Some(Document::Expected(1u64)),
+                        Some(Document::Expected(2)),
+                        Some(Document::Unexpected(text.clone())),
+                        None,
+                        Some(Document::Expected(4))];
+        let kept: Vec<_> = response(docs)
+            .filter_ok(|n| n % 2 == 0)
+            .wait()
+            .map(|doc| match doc.unwrap() {
+                     Some(Document::Expected(n)) => Some(Ok(n)),
+                     Some(Document::Unexpected(value)) => Some(Err(value)),
+                     None => None,
+                 })
+            .collect();
+        assert_eq!(kept, vec![Some(Ok(2)), Some(Err(text)), None, Some(Ok(4))]);
+    }
+
+    #[test]
+    fn into_vec_with_profile_returns_the_rows_and_the_profile()
+    {
+        let docs = vec![Some(Document::Expected(1u64)), None, Some(Document::Expected(2))];
+        let profile: Value = from_str(r#"[{"description": "Evaluating datum.", "duration(ms)": 0.01}]"#).unwrap();
+        let res = response(docs.clone());
+        *res.profile.write() = Some(profile.clone());
+        assert_eq!(res.into_vec_with_profile().unwrap(), (vec![1, 2], Some(profile)));
+        assert_eq!(response(docs).into_vec_with_profile().unwrap(), (vec![1, 2], None));
+    }
+
+    #[test]
+    fn split_initial_stops_at_the_ready_state()
+    {
+        let changes = [r#"{"state": "initializing"}"#,
+                       r#"{"new_val": 1}"#,
+                       r#"{"new_val": 2}"#,
+                       r#"{"state": "ready"}"#,
+                       r#"{"old_val": null, "new_val": 3}"#];
+        let docs = changes.iter()
+            .map(|change| Some(Document::Expected(from_str::<Change<u64, u64>>(change).unwrap())))
+            .collect();
+        let (initial, rest) = response(docs).split_initial().unwrap();
+        assert_eq!(initial, vec![1, 2]);
+        let live: Vec<_> = rest.wait()
+            .map(|doc| match doc.unwrap() {
+                     Some(Document::Expected(change)) => change.new_val,
+                     doc => panic!("expected a change, got {:?}", doc),
+                 })
+            .collect();
+        assert_eq!(live, vec![Some(3)]);
+    }
+}
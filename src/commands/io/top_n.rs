@@ -0,0 +1,143 @@
+use {Arg, Client, FeedItem, IntoArg, Result, TopN};
+use errors::{DriverError, Error};
+use futures::{Async, Poll, Stream};
+use reql_types::Change;
+use serde::de::DeserializeOwned;
+use std::cmp;
+
+pub fn top_n_feed<A, T>(client: &Client, args: A) -> Result<TopN<T>>
+    where A: IntoArg,
+          T: DeserializeOwned + Send + Clone + 'static
+{
+    let feed = client.changes()
+        .with_args(args!({include_initial: true, include_offsets: true, include_states: true}))
+        .run_resilient::<_, Change<T, T>>(args)?;
+    Ok(TopN {
+           feed: feed,
+           rows: Vec::new(),
+           ready: false,
+       })
+}
+
+impl<T: DeserializeOwned + Send + Clone + 'static> TopN<T>
+{
+    fn apply(&mut self, change: Change<T, T>)
+    {
+        if let Some(i) = change.old_offset {
+            if i < self.rows.len() {
+                self.rows.remove(i);
+            }
+        }
+        if let (Some(i), Some(doc)) = (change.new_offset, change.new_val) {
+            let i = cmp::min(i, self.rows.len());
+            self.rows.insert(i, doc);
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Send + Clone + 'static> Stream for TopN<T>
+{
+    type Item = Vec<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error>
+    {
+        loop {
+            match self.feed.poll()? {
+                Async::NotReady => return Ok(Async::NotReady),
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::Ready(Some(FeedItem::Doc(Some(doc)))) => {
+                    // Skipping a change would leave the window out of step
+                    // with the one on the server
+                    let change = doc.into_result()?;
+                    match change.state {
+                        // The initial window is complete
+                        Some(ref state) if state == "ready" => {
+                            self.ready = true;
+                            return Ok(Async::Ready(Some(self.rows.clone())));
+                        }
+                        Some(_) => continue,
+                        None => {}
+                    }
+                    self.apply(change);
+                    if self.ready {
+                        return Ok(Async::Ready(Some(self.rows.clone())));
+                    }
+                }
+                // The restarted feed sends the whole window again
+                Async::Ready(Some(FeedItem::Gap)) => {
+                    self.rows.clear();
+                    self.ready = false;
+                }
+                // A change is never null
+                Async::Ready(Some(FeedItem::Doc(None))) => {
+                    let msg = String::from("unexpected response from server: null");
+                    return Err(DriverError::Deserialize(msg))?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Document, ResilientFeed, Response};
+    use futures::Sink;
+    use futures::sync::mpsc;
+    use parking_lot::RwLock;
+    use serde_json::{Value, from_str};
+    use std::sync::Arc;
+
+    // A window fed with the given changes, as if they came from the server
+    fn window(changes: Vec<Option<Document<Change<u64, u64>>>>) -> TopN<u64>
+    {
+        let (tx, rx) = mpsc::channel(changes.len());
+        let mut tx = tx.wait();
+        for change in changes {
+            tx.send(Ok(change)).unwrap();
+        }
+        let response = Response {
+            done: false,
+            rx: rx,
+            profile: Arc::new(RwLock::new(None)),
+            backlog: None,
+        };
+        TopN {
+            feed: ResilientFeed {
+                client: Client::new(),
+                arg: args!(),
+                response: response,
+            },
+            rows: Vec::new(),
+            ready: false,
+        }
+    }
+
+    fn change(json: &str) -> Option<Document<Change<u64, u64>>>
+    {
+        Some(Document::Expected(from_str(json).unwrap()))
+    }
+
+    #[test]
+    fn the_window_follows_the_offsets_of_the_changes()
+    {
+        let top = window(vec![change(r#"{"new_val": 30, "new_offset": 0}"#),
+                              change(r#"{"new_val": 20, "new_offset": 1}"#),
+                              change(r#"{"state": "ready"}"#),
+                              change(r#"{"old_val": 20, "old_offset": 1, "new_val": 40, "new_offset": 0}"#)]);
+        let windows: Vec<Vec<u64>> = top.wait().map(|window| window.unwrap()).collect();
+        assert_eq!(windows, vec![vec![30, 20], vec![40, 30]]);
+    }
+
+    #[test]
+    fn unexpected_changes_are_errors()
+    {
+        let mut top = window(vec![change(r#"{"new_val": 30, "new_offset": 0}"#),
+                                  Some(Document::Unexpected(Value::String(String::from("30"))))])
+                .wait();
+        assert!(top.next().unwrap().is_err());
+        let mut top = window(vec![None]).wait();
+        assert!(top.next().unwrap().is_err());
+    }
+}
@@ -1,7 +1,9 @@
-use {Client, IntoArg, slog};
+use {Arg, Client, IntoArg, Json, Result, slog};
+use errors::DriverError;
 use protobuf::repeated::RepeatedField;
-use ql2::proto::Term;
+use ql2::proto::{Datum_DatumType, Term, Term_AssocPair as TermPair};
 use ql2::proto::Term_TermType;
+use serde::Serialize;
 
 pub fn new_client() -> Client
 {
@@ -10,6 +12,7 @@ pub fn new_client() -> Client
         query: String::from("r"),
         write: false,
         logger: slog::Logger::root(slog::Discard, o!()),
+        run_opts: Ok(Term::new()),
     }
 }
 
@@ -34,8 +37,9 @@ pub fn make_cmd<A: IntoArg>(client: &Client,
         let prev_cmd = RepeatedField::from_vec(vec![cterm.clone()]);
         term.set_args(prev_cmd);
     }
-    let mut cmd = Client::new();
+    let mut cmd = Client::new().with_logger(logger);
     cmd.term = Ok(term);
+    cmd.run_opts = client.run_opts.clone();
     match args {
         Some(args) => {
             let arg = args.into_arg();
@@ -47,7 +51,34 @@ pub fn make_cmd<A: IntoArg>(client: &Client,
                     return cmd;
                 }
             };
+            let aterm = match name {
+                "filter" => object_arg(aterm, &["default"]),
+                "insert" => object_arg(aterm, &["durability", "return_changes", "conflict"]),
+                "update" => object_arg(aterm, &["durability", "return_changes", "non_atomic"]),
+                "merge" => object_arg(aterm, &[]),
+                "with_fields" | "has_fields" | "pluck" | "without" => nested_fields(aterm),
+                _ => aterm,
+            };
+            let valid = match name {
+                "db" | "table" => validate_name(name, &aterm),
+                "limit" | "skip" => validate_count(name, &aterm),
+                "branch" => validate_branch(cterm != Term::new(), &aterm),
+                "eq" | "ne" | "gt" | "ge" | "lt" | "le" => validate_comparison(name, cterm != Term::new(), &aterm),
+                "union" => validate_interleave(&aterm),
+                "http" => validate_http(&aterm),
+                "update" | "replace" => validate_atomic(name, &aterm),
+                _ => Ok(()),
+            };
+            if let Err(error) = valid {
+                cmd.term = Err(error);
+                return cmd;
+            }
             with_args!(cmd, aterm);
+            if name == "do_" {
+                move_func_first(&mut cmd);
+            } else if name == "reduce" {
+                reduce_default(&mut cmd);
+            }
         }
         None => {
             cmd.query = format!("{}.{}()", client.query, name);
@@ -55,7 +86,38 @@ pub fn make_cmd<A: IntoArg>(client: &Client,
     }
     debug!(cmd.logger, "{}", cmd.query);
     debug!(cmd.logger, "{:?}", cmd.term);
-    cmd.with_logger(logger)
+    cmd
+}
+
+pub fn get_path(client: &Client, path: &[&str]) -> Client
+{
+    path.iter().fold(client.clone(), |cmd, field| cmd.get_field(*field))
+}
+
+// Orders the documents by a UUID derived from the seed and their primary key,
+// which looks random but is the same every time for the same seed
+pub fn sample_seeded(client: &Client, n: u64, seed: &str) -> Client
+{
+    let r = new_client();
+    let seed = seed.to_string();
+    let order = func!((|row: Client| {
+                           let name = r.expr(seed.as_str()).add(row.get_field("id").coerce_to("string"));
+                           r.uuid_of(name)
+                       }),
+                      var!());
+    client.order_by(order).limit(n)
+}
+
+// An `insert` that updates the documents that already exist instead of
+// failing on them
+pub fn upsert<T: IntoArg>(client: &Client, docs: T) -> Client
+{
+    client.insert(docs).with_args(args!({conflict: "update"}))
+}
+
+pub fn expr_serialize<T: Serialize>(client: &Client, value: T) -> Client
+{
+    client.expr(Json(value))
 }
 
 pub fn with_logger(client: &Client, logger: slog::Logger) -> Client
@@ -65,6 +127,46 @@ pub fn with_logger(client: &Client, logger: slog::Logger) -> Client
     cmd
 }
 
+// Adds the options to the client's default run options, replacing the ones
+// it already has with the same name
+pub fn with_default_run_opts<A: IntoArg>(client: &Client, opts: A) -> Client
+{
+    let mut cmd = client.clone();
+    match opts.into_arg().term {
+        Ok(mut term) => set_run_opts(&mut cmd, term.take_optargs().into_vec()),
+        Err(error) => cmd.run_opts = Err(error),
+    }
+    cmd
+}
+
+// Sets the `db` among the client's default run options, keeping the others
+pub fn use_db(client: &Client, name: &str) -> Client
+{
+    let mut cmd = client.clone();
+    if let Err(error) = new_client().db(name).term {
+        cmd.run_opts = Err(error);
+        return cmd;
+    }
+    match Arg::create_term_pair("db", name) {
+        Ok(pair) => set_run_opts(&mut cmd, vec![pair]),
+        Err(error) => cmd.run_opts = Err(error),
+    }
+    cmd
+}
+
+fn set_run_opts(cmd: &mut Client, pairs: Vec<TermPair>)
+{
+    if let Ok(ref mut term) = cmd.run_opts {
+        let mut optargs: Vec<TermPair> = term.take_optargs()
+            .into_vec()
+            .into_iter()
+            .filter(|old| pairs.iter().all(|pair| pair.get_key() != old.get_key()))
+            .collect();
+        optargs.extend(pairs);
+        term.set_optargs(RepeatedField::from_vec(optargs));
+    }
+}
+
 pub fn with_args<A: IntoArg>(client: &Client, args: A) -> Client
 {
     let mut cmd = client.clone();
@@ -81,8 +183,851 @@ pub fn with_args<A: IntoArg>(client: &Client, args: A) -> Client
         }
     };
     let logger = cmd.logger.new(o!("command" => "with_args"));
-    with_args!(cmd, aterm);
+    let valid = match cmd.term {
+        Ok(ref term) if term.get_field_type() == Term_TermType::CHANGES => validate_squash(&aterm),
+        Ok(ref term) if term.get_field_type() == Term_TermType::HTTP => validate_http(&aterm),
+        _ => Ok(()),
+    };
+    if let Err(error) = valid {
+        cmd.term = Err(error);
+        return cmd;
+    }
+    let indexed = match cmd.term {
+        Ok(ref term) => indexed_count(term, &aterm),
+        Err(_) => None,
+    };
+    match indexed {
+        Some(term) => {
+            // Show the query that is actually sent
+            if client.query.ends_with(".count()") {
+                let seq = &client.query[..client.query.len() - ".count()".len()];
+                cmd.query = format!("{}.between(r.minval(), r.maxval(), {}).count()", seq, args.string);
+            }
+            cmd.term = Ok(term);
+        }
+        None => with_args!(cmd, aterm),
+    }
     debug!(logger, "{}", cmd.query);
     debug!(logger, "{:?}", cmd.term);
     cmd.with_logger(logger)
 }
+
+// Check database and table names given as strings so we don't have to
+// wait for the server to reject them
+fn validate_name(cmd: &str, aterm: &Term) -> Result<()>
+{
+    if let Some(term) = first_arg(aterm) {
+        if term.get_field_type() == Term_TermType::DATUM &&
+           term.get_datum().get_field_type() == Datum_DatumType::R_STR {
+            let name = term.get_datum().get_r_str();
+            // Same rules as the server
+            let is_valid = !name.is_empty() &&
+                           name.chars().all(|c| match c {
+                                                'A'...'Z' | 'a'...'z' | '0'...'9' | '_' | '-' => true,
+                                                _ => false,
+                                            });
+            if !is_valid {
+                let msg = format!("invalid {} name `{}`, use A-Z, a-z, 0-9, _ and - only",
+                                  cmd,
+                                  name);
+                return Err(DriverError::Other(msg))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Unlike `slice` and `nth`, `limit` and `skip` don't count from the end
+// so a negative number is always a mistake
+fn validate_count(cmd: &str, aterm: &Term) -> Result<()>
+{
+    if let Some(term) = first_arg(aterm) {
+        if term.get_field_type() == Term_TermType::DATUM &&
+           term.get_datum().get_field_type() == Datum_DatumType::R_NUM {
+            let num = term.get_datum().get_r_num();
+            if num < 0.0 {
+                let msg = format!("`{}` requires a non-negative number, found {}", cmd, num);
+                return Err(DriverError::Other(msg))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// `interleave` is either a bool or the field to merge-sort on, given as a
+// string or as a function returning one
+fn validate_interleave(aterm: &Term) -> Result<()>
+{
+    for pair in aterm.get_optargs() {
+        if pair.get_key() != "interleave" {
+            continue;
+        }
+        let val = optarg_val(pair);
+        if val.get_field_type() == Term_TermType::DATUM {
+            match val.get_datum().get_field_type() {
+                Datum_DatumType::R_BOOL | Datum_DatumType::R_STR => {}
+                typ => {
+                    let msg = format!("`interleave` must be a bool, a field name or a function, found {:?}",
+                                      typ);
+                    return Err(DriverError::Other(msg))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// ReQL expects the function of `do` to come first but, like in the
+// official drivers, it reads better when given last
+fn move_func_first(cmd: &mut Client)
+{
+    if let Ok(ref mut term) = cmd.term {
+        let mut args = term.take_args().into_vec();
+        if let Some(func) = args.pop() {
+            args.insert(0, func);
+        }
+        term.set_args(RepeatedField::from_vec(args));
+    }
+}
+
+// `reduce` fails on an empty sequence, so a `default` given to it, like
+// `reduce(args!(|a, b| a.add(b), {default: 0}))`, is sent as
+// `reduce(...).default(0)` and returned for empty sequences instead
+fn reduce_default(cmd: &mut Client)
+{
+    if let Ok(ref mut term) = cmd.term {
+        let mut base = None;
+        let mut optargs = Vec::new();
+        for pair in term.take_optargs().into_vec() {
+            if pair.get_key() == "default" {
+                base = Some(pair);
+            } else {
+                optargs.push(pair);
+            }
+        }
+        term.set_optargs(RepeatedField::from_vec(optargs));
+        if let Some(mut pair) = base {
+            let mut val = pair.take_val();
+            if !val.has_field_type() && val.get_args().len() == 1 {
+                val = val.take_args().into_vec().remove(0);
+            }
+            let mut default = Term::new();
+            default.set_field_type(Term_TermType::DEFAULT);
+            default.set_args(RepeatedField::from_vec(vec![term.clone(), val]));
+            *term = default;
+        }
+    }
+}
+
+// A numeric `squash` is the number of seconds to wait for changes to
+// coalesce, which can't be negative
+fn validate_squash(aterm: &Term) -> Result<()>
+{
+    for pair in aterm.get_optargs() {
+        if pair.get_key() != "squash" {
+            continue;
+        }
+        let val = optarg_val(pair);
+        if val.get_field_type() == Term_TermType::DATUM {
+            let datum = val.get_datum();
+            let is_valid = match datum.get_field_type() {
+                Datum_DatumType::R_BOOL => true,
+                Datum_DatumType::R_NUM => datum.get_r_num() >= 0.0,
+                _ => false,
+            };
+            if !is_valid {
+                let msg = String::from("squash must be a boolean or non-negative number");
+                return Err(DriverError::Other(msg))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The server follows pages while `page` returns the next URL and streams
+// the results of every page as a sequence. It needs `page_limit` to know
+// when to give up, and a `page` function is called with a single object
+// holding the last request's `params` and the response's `header` and `body`.
+fn validate_http(aterm: &Term) -> Result<()>
+{
+    let mut page = None;
+    let mut has_limit = false;
+    for pair in aterm.get_optargs() {
+        match pair.get_key() {
+            "page" => page = Some(optarg_val(pair)),
+            "page_limit" => has_limit = true,
+            _ => {}
+        }
+    }
+    let msg = match page {
+        Some(_) if !has_limit => "`page` must be given together with `page_limit`",
+        None if has_limit => "`page_limit` must be given together with `page`",
+        Some(func) if func.get_field_type() == Term_TermType::FUNC && func_arity(func) != 1 => {
+            "a `page` function takes a single parameter"
+        }
+        _ => return Ok(()),
+    };
+    Err(DriverError::Other(String::from(msg)))?;
+    Ok(())
+}
+
+// The number of parameters of a `FUNC` term
+fn func_arity(func: &Term) -> usize
+{
+    match func.get_args().first() {
+        Some(params) => params.get_datum().get_r_array().len(),
+        None => 0,
+    }
+}
+
+// The value of an optional argument. `args!` wraps it in terms without a
+// type, which are left out when the query is encoded.
+fn optarg_val(pair: &TermPair) -> &Term
+{
+    let mut val = pair.get_val();
+    while !val.has_field_type() && !val.has_datum() && val.get_args().len() == 1 {
+        val = &val.get_args()[0];
+    }
+    val
+}
+
+fn first_arg(aterm: &Term) -> Option<&Term>
+{
+    if aterm.has_field_type() {
+        Some(aterm)
+    } else {
+        aterm.get_args().first()
+    }
+}
+
+// An object on its own, like `filter(args!({status: "active"}))` or
+// `update(args!({temp: r.literal()}))`, comes out of `args!` as optional
+// arguments. The keys the command doesn't take as options are turned back
+// into an object and sent as its argument instead. For `filter` that object
+// matches documents whose fields, including nested ones, are equal to it.
+fn object_arg(mut aterm: Term, optargs: &[&str]) -> Term
+{
+    if aterm.has_field_type() || !aterm.get_args().is_empty() {
+        return aterm;
+    }
+    let mut object = Term::new();
+    for pair in aterm.take_optargs().into_vec() {
+        if optargs.contains(&pair.get_key()) {
+            aterm.mut_optargs().push(pair);
+        } else {
+            object.mut_optargs().push(pair);
+        }
+    }
+    if !object.get_optargs().is_empty() {
+        aterm.mut_args().push(object);
+    }
+    aterm
+}
+
+// Nested field specs, like `with_fields(args!("id", {contact: {phone: "work"}}))`,
+// also come out of `args!` as optional arguments. None of these commands take
+// any options, so the object is always sent as the last field instead.
+fn nested_fields(mut aterm: Term) -> Term
+{
+    if aterm.has_field_type() || aterm.get_optargs().is_empty() {
+        return aterm;
+    }
+    let mut object = Term::new();
+    object.set_optargs(aterm.take_optargs());
+    aterm.mut_args().push(object);
+    aterm
+}
+
+// Count the arguments a command is called with, including the previous
+// command when chained
+fn count_args(chained: bool, aterm: &Term) -> Option<usize>
+{
+    let args: Vec<&Term> = if aterm.has_field_type() {
+        vec![aterm]
+    } else {
+        aterm.get_args().iter().collect()
+    };
+    // We can't tell how many arguments `r.args` will splice in
+    if args.iter().any(|arg| arg.get_field_type() == Term_TermType::ARGS) {
+        return None;
+    }
+    if chained {
+        Some(args.len() + 1)
+    } else {
+        Some(args.len())
+    }
+}
+
+// Comparisons are variadic, `r.lt(1, 2, 3)` checks that the values are
+// strictly increasing, but they need at least two values to compare
+fn validate_comparison(cmd: &str, chained: bool, aterm: &Term) -> Result<()>
+{
+    if let Some(count) = count_args(chained, aterm) {
+        if count < 2 {
+            let msg = format!("`{}` takes at least two values to compare, found {}",
+                              cmd,
+                              count);
+            return Err(DriverError::Other(msg))?;
+        }
+    }
+    Ok(())
+}
+
+// Writes are atomic by default, which the server can only guarantee when
+// the new values are deterministic. Catch the common cases before sending
+// the query so the error can point at `non_atomic`.
+fn validate_atomic(cmd: &str, aterm: &Term) -> Result<()>
+{
+    for pair in aterm.get_optargs() {
+        if pair.get_key() != "non_atomic" {
+            continue;
+        }
+        let val = optarg_val(pair);
+        let datum = val.get_datum();
+        let is_false = val.get_field_type() == Term_TermType::DATUM &&
+                       datum.get_field_type() == Datum_DatumType::R_BOOL &&
+                       !datum.get_r_bool();
+        if !is_false {
+            return Ok(());
+        }
+    }
+    let args: Vec<&Term> = if aterm.has_field_type() {
+        vec![aterm]
+    } else {
+        aterm.get_args().iter().collect()
+    };
+    if let Some(name) = args.into_iter().filter_map(non_deterministic).next() {
+        let msg = format!("`{}` can't be done atomically with `{}`, set `non_atomic: true` to run it anyway",
+                          cmd,
+                          name);
+        return Err(DriverError::Other(msg))?;
+    }
+    Ok(())
+}
+
+// Find a command whose value changes every time it runs
+fn non_deterministic(term: &Term) -> Option<&'static str>
+{
+    match term.get_field_type() {
+        Term_TermType::RANDOM => Some("r.random()"),
+        Term_TermType::JAVASCRIPT => Some("r.js()"),
+        Term_TermType::HTTP => Some("r.http()"),
+        Term_TermType::UUID if term.get_args().is_empty() => Some("r.uuid()"),
+        _ => {
+            term.get_args()
+                .iter()
+                .filter_map(non_deterministic)
+                .next()
+                .or_else(|| {
+                    term.get_optargs()
+                        .iter()
+                        .filter_map(|pair| non_deterministic(pair.get_val()))
+                        .next()
+                })
+        }
+    }
+}
+
+// `branch` takes pairs of tests and values followed by a default value
+fn validate_branch(chained: bool, aterm: &Term) -> Result<()>
+{
+    // When chained the previous command is the first test
+    let count = match count_args(chained, aterm) {
+        Some(count) => count,
+        None => return Ok(()),
+    };
+    if count < 3 || count % 2 == 0 {
+        let msg = format!("`branch` takes pairs of tests and values followed by a default value, found {} arguments",
+                          count);
+        return Err(DriverError::Other(msg))?;
+    }
+    Ok(())
+}
+
+// A `count` given an `index` is sent as `between(r.minval, r.maxval, {index})`
+// followed by `count` so the server reads the index rather than scanning
+// every document. A `count` with a predicate still has to scan.
+fn indexed_count(term: &Term, aterm: &Term) -> Option<Term>
+{
+    if term.get_field_type() != Term_TermType::COUNT || term.get_args().len() != 1 {
+        return None;
+    }
+    if aterm.has_field_type() || !aterm.get_args().is_empty() {
+        return None;
+    }
+    if !aterm.get_optargs().iter().any(|pair| pair.get_key() == "index") {
+        return None;
+    }
+    let mut minval = Term::new();
+    minval.set_field_type(Term_TermType::MINVAL);
+    let mut maxval = Term::new();
+    maxval.set_field_type(Term_TermType::MAXVAL);
+    let mut between = Term::new();
+    between.set_field_type(Term_TermType::BETWEEN);
+    between.set_args(RepeatedField::from_vec(vec![term.get_args()[0].clone(), minval, maxval]));
+    let optargs: Vec<TermPair> = aterm.get_optargs().to_vec();
+    between.set_optargs(RepeatedField::from_vec(optargs));
+    let mut count = Term::new();
+    count.set_field_type(Term_TermType::COUNT);
+    count.set_args(RepeatedField::from_vec(vec![between]));
+    Some(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protobuf::ProtobufEnum;
+    use serde_json::{Value, from_str};
+    use std::sync::{Arc, Mutex};
+    use types::{Encode, FromJson};
+
+    #[test]
+    fn uuid_of_passes_the_name()
+    {
+        let r = Client::new();
+        let term = r.uuid_of("slava@example.com").term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::UUID);
+        assert_eq!(term.get_args().to_vec(), vec![Term::from_json("slava@example.com").unwrap()]);
+        assert_eq!(term, r.uuid().with_args("slava@example.com").term.unwrap());
+    }
+
+    #[test]
+    fn count_with_an_index_reads_the_index()
+    {
+        let r = Client::new();
+        let cmd = r.table("users").count().with_args(args!({index: "age"}));
+        let between = r.table("users").between(args!(r.minval(), r.maxval(), {index: "age"}));
+        assert_eq!(cmd.term.unwrap(), between.count().term.unwrap());
+        let seq = r.table("users").query;
+        assert!(cmd.query.starts_with(&format!("{}.between(r.minval(), r.maxval(), ", seq)));
+        assert!(cmd.query.ends_with(").count()"));
+    }
+
+    #[test]
+    fn count_with_a_predicate_is_left_alone()
+    {
+        let r = Client::new();
+        let cmd = r.table("users").count().with_args(args!(true));
+        let term = cmd.term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::COUNT);
+        assert_eq!(term.get_args()[0].get_field_type(), Term_TermType::TABLE);
+    }
+
+    #[test]
+    fn db_and_table_names_are_checked()
+    {
+        let r = Client::new();
+        assert!(r.db("heroes_2-0").table("marvel").term.is_ok());
+        assert!(r.table("").term.is_err());
+        assert!(r.table("super heroes").term.is_err());
+        assert!(r.db("heroes.marvel").term.is_err());
+        // Names computed by the query are left to the server
+        assert!(r.table(r.expr("super").add(" heroes")).term.is_ok());
+    }
+
+    #[test]
+    fn limit_and_skip_refuse_negative_counts()
+    {
+        let r = Client::new();
+        assert!(r.table("marvel").limit(10).skip(0).term.is_ok());
+        assert!(r.table("marvel").limit(-1).term.is_err());
+        assert!(r.table("marvel").skip(-5).term.is_err());
+        // These count from the end
+        assert!(r.table("marvel").nth(-1).term.is_ok());
+        assert!(r.table("marvel").slice(-3).term.is_ok());
+    }
+
+    #[test]
+    fn branch_takes_pairs_and_a_default()
+    {
+        let r = Client::new();
+        let status = r.expr(404);
+        let label = r.branch(args!(status.clone().eq(200), "ok",
+                                   status.clone().eq(404), "not found",
+                                   status.clone().eq(500), "error",
+                                   "unknown"));
+        assert_eq!(label.term.unwrap().get_args().len(), 7);
+        assert!(r.branch(args!(true, "yes")).term.is_err());
+        assert!(r.branch(args!(true, "yes", false, "no")).term.is_err());
+        // Chained, the previous command is the first test
+        assert!(r.expr(true).branch(args!("yes", "no")).term.is_ok());
+        assert!(r.expr(true).branch(args!("yes")).term.is_err());
+    }
+
+    #[test]
+    fn nested_objects_are_sent_as_objects()
+    {
+        let r = Client::new();
+        let cmd = r.table("heroes")
+            .reconfigure(args!({shards: 2, replicas: {east: 2, west: 1}, primary_replica_tag: "east"}));
+        let expected = format!(r#"[{}, [[{}, ["heroes"]]],
+                                   {{"shards": 2, "replicas": {{"east": 2, "west": 1}}, "primary_replica_tag": "east"}}]"#,
+                               Term_TermType::RECONFIGURE.value(),
+                               Term_TermType::TABLE.value());
+        let encoded = cmd.term.unwrap().encode();
+        assert_eq!(from_str::<Value>(&encoded).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn do_sends_the_function_first()
+    {
+        let r = Client::new();
+        let cmd = r.do_(args!(r.expr(5), |x| x.clone().mul(x)));
+        let term = cmd.term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::FUNCALL);
+        let args = term.get_args();
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[0].get_field_type(), Term_TermType::FUNC);
+        assert_eq!(args[1].get_field_type(), Term_TermType::DATUM);
+        // Chained, the previous command is the value
+        let term = r.expr(5).do_(args!(|x| x.clone().mul(x))).term.unwrap();
+        assert_eq!(term.get_args()[0].get_field_type(), Term_TermType::FUNC);
+        assert_eq!(term.get_args()[1].get_field_type(), Term_TermType::DATUM);
+    }
+
+    #[test]
+    fn squash_is_a_bool_or_a_non_negative_number()
+    {
+        let r = Client::new();
+        let feed = r.table("marvel").changes();
+        assert!(feed.with_args(args!({squash: 1.5})).term.is_ok());
+        assert!(feed.with_args(args!({squash: true})).term.is_ok());
+        let negative = -1.5;
+        assert!(feed.with_args(args!({squash: negative})).term.is_err());
+        assert!(feed.with_args(args!({squash: "1.5"})).term.is_err());
+    }
+
+    #[test]
+    fn comparisons_take_at_least_two_values()
+    {
+        let r = Client::new();
+        assert!(r.lt(args!(1, 2, 3)).term.is_ok());
+        assert!(r.lt(1).term.is_err());
+        // Chained, the previous command is the first value
+        assert!(r.expr(1).lt(2).term.is_ok());
+        assert!(r.expr(1).eq(args!(1, 1)).term.is_ok());
+    }
+
+    #[test]
+    fn filter_sends_an_object_as_the_predicate()
+    {
+        let r = Client::new();
+        let term = r.table("users").filter(args!({status: "active", tier: "gold"})).term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::FILTER);
+        assert!(term.get_optargs().is_empty());
+        let args = term.get_args();
+        assert_eq!(args.len(), 2);
+        let keys: Vec<_> = args[1].get_optargs().iter().map(|pair| pair.get_key()).collect();
+        assert_eq!(keys, vec!["status", "tier"]);
+    }
+
+    #[test]
+    fn filter_keeps_nested_objects_and_the_default_option()
+    {
+        let r = Client::new();
+        let cmd = r.table("users").filter(args!({contact: {city: "Paris"}, default: true}));
+        let expected = format!(r#"[{}, [[{}, ["users"]], {{"contact": {{"city": "Paris"}}}}], {{"default": true}}]"#,
+                               Term_TermType::FILTER.value(),
+                               Term_TermType::TABLE.value());
+        let encoded = cmd.term.unwrap().encode();
+        assert_eq!(from_str::<Value>(&encoded).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn update_sends_an_object_and_keeps_its_options()
+    {
+        let r = Client::new();
+        let user = r.table("users").get(1);
+        let term = user.update(args!({temp_field: r.literal(), durability: "soft"})).term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::UPDATE);
+        let args = term.get_args();
+        assert_eq!(args.len(), 2);
+        let object = args[1].get_optargs();
+        assert_eq!(object.len(), 1);
+        assert_eq!(object[0].get_key(), "temp_field");
+        assert_eq!(optarg_val(&object[0]).get_field_type(), Term_TermType::LITERAL);
+        let opts = term.get_optargs();
+        assert_eq!(opts.len(), 1);
+        assert_eq!(opts[0].get_key(), "durability");
+    }
+
+    #[test]
+    fn merge_sends_the_whole_object()
+    {
+        let r = Client::new();
+        let term = r.table("users").get(1).merge(args!({temp_field: r.literal()})).term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::MERGE);
+        assert!(term.get_optargs().is_empty());
+        assert_eq!(term.get_args()[1].get_optargs()[0].get_key(), "temp_field");
+    }
+
+    #[test]
+    fn default_run_opts_are_merged_with_the_db()
+    {
+        // `args!` doesn't keep the order of the keys of an object
+        let keys = |r: &Client| -> Vec<String> {
+            let mut keys: Vec<String> =
+                r.run_opts.as_ref().unwrap().get_optargs().iter().map(|pair| pair.get_key().to_string()).collect();
+            keys.sort();
+            keys
+        };
+        let r = Client::new().use_db("blog").with_default_run_opts(args!({read_mode: "outdated"}));
+        assert_eq!(keys(&r), vec!["db", "read_mode"]);
+        let r = r.with_default_run_opts(args!({read_mode: "majority", durability: "soft"})).use_db("heroes");
+        assert_eq!(keys(&r), vec!["db", "durability", "read_mode"]);
+        let opts = r.run_opts.as_ref().unwrap().get_optargs();
+        let read_mode = opts.iter().find(|pair| pair.get_key() == "read_mode").unwrap();
+        assert_eq!(optarg_val(read_mode).get_datum().get_r_str(), "majority");
+        assert_eq!(keys(&r.table("posts")), keys(&r));
+        assert!(Client::new().use_db("not a name").run_opts.is_err());
+    }
+
+    #[test]
+    fn http_takes_a_page_function_with_a_page_limit()
+    {
+        let r = Client::new();
+        let url = "http://example.com/pages";
+        let term = r.http(args!(url, {page: |info| info.get_field("body").get_field("next"), page_limit: 3}))
+            .term
+            .unwrap();
+        let page = term.get_optargs().iter().find(|pair| pair.get_key() == "page").unwrap();
+        let func = optarg_val(page);
+        assert_eq!(func.get_field_type(), Term_TermType::FUNC);
+        assert_eq!(func_arity(func), 1);
+        assert!(r.http(args!(url, {page: "link-next"})).term.is_err());
+        assert!(r.http(args!(url, {page_limit: 3})).term.is_err());
+        assert!(r.http(args!(url, {page: |a, b| a.add(b), page_limit: 3})).term.is_err());
+        assert!(r.http(url).with_args(args!({page: "link-next", page_limit: 3})).term.is_ok());
+    }
+
+    #[test]
+    fn union_checks_the_interleave_option()
+    {
+        let r = Client::new();
+        let marvel = r.table("marvel").order_by(args!({index: "name"}));
+        let dc = r.table("dc").order_by(args!({index: "name"}));
+        assert!(marvel.union(args!(dc.clone(), {interleave: "name"})).term.is_ok());
+        assert!(marvel.union(args!(dc.clone(), {interleave: false})).term.is_ok());
+        assert!(marvel.union(args!(dc.clone(), {interleave: |hero| hero.get_field("name")})).term.is_ok());
+        assert!(marvel.union(args!(dc, {interleave: 1})).term.is_err());
+    }
+
+    #[test]
+    fn replace_with_a_random_value_needs_non_atomic()
+    {
+        let r = Client::new();
+        let post = r.table("posts").get(1);
+        let allowed = post.replace(args!(|post| post.merge(args!({token: r.uuid()})), {non_atomic: true}));
+        assert!(allowed.term.is_ok());
+        let refused = post.replace(args!(|post| post.merge(args!({token: r.uuid()})), {non_atomic: false}));
+        assert!(refused.term.is_err());
+        assert!(post.replace(args!(|post| post.merge(args!({token: r.uuid()})))).term.is_err());
+        // `r.now()` is the same everywhere in a query so it's fine on its own
+        assert!(post.replace(args!(|post| post.merge(args!({seen_at: r.now()})))).term.is_ok());
+    }
+
+    #[test]
+    fn insert_takes_a_conflict_function_of_three_parameters()
+    {
+        let r = Client::new();
+        let term = r.table("posts")
+            .insert(args!({id: 1, title: "Hello"}, {conflict: |_id, old, new| old.merge(new), durability: "soft"}))
+            .term
+            .unwrap();
+        assert_eq!(term.get_optargs().len(), 2);
+        let conflict = term.get_optargs().iter().find(|pair| pair.get_key() == "conflict").unwrap();
+        let func = optarg_val(conflict);
+        assert_eq!(func.get_field_type(), Term_TermType::FUNC);
+        assert_eq!(func_arity(func), 3);
+        assert_eq!(func.get_args()[1].get_field_type(), Term_TermType::MERGE);
+    }
+
+    #[test]
+    fn order_by_takes_the_direction_of_an_index()
+    {
+        let r = Client::new();
+        for &(ref order, direction) in &[(r.asc("date"), Term_TermType::ASC), (r.desc("date"), Term_TermType::DESC)] {
+            let term = r.table("posts").order_by(args!({index: order.clone()})).term.unwrap();
+            let index = optarg_val(&term.get_optargs()[0]);
+            assert_eq!(index.get_field_type(), direction);
+            assert_eq!(index.encode(), format!("[{},[\"date\"]]", direction.value()));
+        }
+    }
+
+    #[test]
+    fn random_takes_a_range_and_the_float_option()
+    {
+        let r = Client::new();
+        let cmd = r.random().with_args(args!(1, 10, {float: true}));
+        let expected = format!(r#"[{}, [1, 10], {{"float": true}}]"#, Term_TermType::RANDOM.value());
+        let encoded = cmd.term.unwrap().encode();
+        assert_eq!(from_str::<Value>(&encoded).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    // Keeps the messages it's given so tests can look at them
+    struct Captured(Arc<Mutex<Vec<String>>>);
+
+    impl slog::Drain for Captured
+    {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(&self, record: &slog::Record, _: &slog::OwnedKVList) -> ::std::result::Result<(), slog::Never>
+        {
+            self.0.lock().unwrap().push(format!("{}", record.msg()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn commands_log_through_the_given_logger()
+    {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let logger = slog::Logger::root(Captured(lines.clone()), o!());
+        let r = Client::new().with_logger(logger);
+        r.table("posts").get(1);
+        let lines = lines.lock().unwrap();
+        assert!(lines.contains(&String::from(r#"r.table("posts")"#)), "{:?}", *lines);
+        assert!(lines.contains(&String::from(r#"r.table("posts").get(1)"#)), "{:?}", *lines);
+    }
+
+    #[test]
+    fn args_splices_a_list_built_at_runtime()
+    {
+        let r = Client::new();
+        let names: Vec<String> = "alice bob".split(' ').map(String::from).collect();
+        let cmd = r.table("people").get_all(r.args(names));
+        assert_eq!(cmd.query, r#"r.table("people").get_all(r.args(["alice", "bob"]))"#);
+        let expected = format!(r#"[{}, [[{}, ["people"]], [{}, [[{}, ["alice", "bob"]]]]]]"#,
+                               Term_TermType::GET_ALL.value(),
+                               Term_TermType::TABLE.value(),
+                               Term_TermType::ARGS.value(),
+                               Term_TermType::MAKE_ARRAY.value());
+        let encoded = cmd.term.unwrap().encode();
+        assert_eq!(from_str::<Value>(&encoded).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn update_sends_a_closure_returning_an_object_as_a_function()
+    {
+        let r = Client::new();
+        let cmd = r.table("counters").get("x").update(args!(|row| args!({n: row.get_field("n").add(1)})));
+        let term = cmd.term.unwrap();
+        let func = &term.get_args()[1];
+        assert_eq!(func.get_field_type(), Term_TermType::FUNC);
+        assert_eq!(func_arity(func), 1);
+        let body = from_str::<Value>(&func.get_args()[1].encode()).unwrap();
+        let n = &body["n"];
+        assert_eq!(n[0], Value::from(Term_TermType::ADD.value()));
+        assert_eq!(n[1][0][0], Value::from(Term_TermType::GET_FIELD.value()));
+        assert_eq!(n[1][0][1][1], Value::from("n"));
+        assert_eq!(n[1][1], Value::from(1));
+    }
+
+    #[test]
+    fn closures_bind_each_parameter_to_its_own_variable()
+    {
+        let r = Client::new();
+        let term = r.expr(Json(vec![1, 2, 3])).reduce(args!(|a, b| a.add(b))).term.unwrap();
+        let func = &term.get_args()[1];
+        assert_eq!(func_arity(func), 2);
+        let params = from_str::<Value>(&func.get_args()[0].encode()).unwrap();
+        let (a, b) = (params[1][0].clone(), params[1][1].clone());
+        assert!(a != b);
+        let body = from_str::<Value>(&func.get_args()[1].encode()).unwrap();
+        let var = Value::from(Term_TermType::VAR.value());
+        assert_eq!(body[1], Value::Array(vec![Value::Array(vec![var.clone(), Value::Array(vec![a])]),
+                                              Value::Array(vec![var, Value::Array(vec![b])])]));
+    }
+
+    #[test]
+    fn get_path_chains_a_get_field_per_level()
+    {
+        let r = Client::new();
+        let path = r.table("users").get(1).get_path(&["address", "city", "name"]);
+        assert_eq!(path.query, r.table("users").get(1).get_field("address").get_field("city").get_field("name").query);
+        let mut term = &path.term.unwrap();
+        for field in &["name", "city", "address"] {
+            assert_eq!(term.get_field_type(), Term_TermType::GET_FIELD);
+            assert_eq!(term.get_args()[1].encode(), format!(r#""{}""#, field));
+            term = &term.get_args()[0];
+        }
+        assert_eq!(term.get_field_type(), Term_TermType::GET);
+    }
+
+    #[test]
+    fn js_sends_its_timeout_as_an_option()
+    {
+        let r = Client::new();
+        let cmd = r.js(args!("1 + 1", {timeout: 1.3}));
+        let expected = format!(r#"[{}, ["1 + 1"], {{"timeout": 1.3}}]"#, Term_TermType::JAVASCRIPT.value());
+        let encoded = cmd.term.unwrap().encode();
+        assert_eq!(from_str::<Value>(&encoded).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn now_is_left_for_the_server_to_evaluate()
+    {
+        let r = Client::new();
+        let term = r.now().sub(r.now()).term.unwrap();
+        let now = format!("[{}]", Term_TermType::NOW.value());
+        let expected = format!("[{}, [{}, {}]]", Term_TermType::SUB.value(), now, now);
+        assert_eq!(from_str::<Value>(&term.encode()).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn between_sends_compound_bounds_as_arrays()
+    {
+        let r = Client::new();
+        let cmd = r.table("posts").between(args!(["books", 10], ["books", 20], {index: "cat_ts"}));
+        let bound = |ts: u32| format!(r#"[{}, ["books", {}]]"#, Term_TermType::MAKE_ARRAY.value(), ts);
+        let expected = format!(r#"[{}, [[{}, ["posts"]], {}, {}], {{"index": "cat_ts"}}]"#,
+                               Term_TermType::BETWEEN.value(),
+                               Term_TermType::TABLE.value(),
+                               bound(10),
+                               bound(20));
+        let encoded = cmd.term.unwrap().encode();
+        assert_eq!(from_str::<Value>(&encoded).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn with_fields_sends_a_nested_spec_as_a_field()
+    {
+        let r = Client::new();
+        let term = r.table("users").with_fields(args!("id", {contact: {phone: "work"}})).term.unwrap();
+        assert!(term.get_optargs().is_empty());
+        let expected = format!(r#"[{}, [[{}, ["users"]], "id", {{"contact": {{"phone": "work"}}}}]]"#,
+                               Term_TermType::WITH_FIELDS.value(),
+                               Term_TermType::TABLE.value());
+        assert_eq!(from_str::<Value>(&term.encode()).unwrap(), from_str::<Value>(&expected).unwrap());
+    }
+
+    #[test]
+    fn reduce_sends_its_default_as_a_default_term()
+    {
+        let r = Client::new();
+        let term = r.table("posts").reduce(args!(|a, b| a.add(b), {default: 0})).term.unwrap();
+        assert_eq!(term.get_field_type(), Term_TermType::DEFAULT);
+        let reduce = &term.get_args()[0];
+        assert_eq!(reduce.get_field_type(), Term_TermType::REDUCE);
+        assert!(reduce.get_optargs().is_empty());
+        assert_eq!(term.get_args()[1].encode(), "0");
+    }
+
+    #[test]
+    fn upsert_is_an_insert_that_updates_on_conflict()
+    {
+        let r = Client::new();
+        let users = r.table("users");
+        let upsert = users.upsert(args!({id: "bob", email: "bob@example.com"})).term.unwrap();
+        assert_eq!(upsert.get_field_type(), Term_TermType::INSERT);
+        let bob = r#"{"id": "bob", "email": "bob@example.com"}"#;
+        let expected = format!(r#"[{}, [[{}, ["users"]], {}], {{"conflict": "update"}}]"#,
+                               Term_TermType::INSERT.value(),
+                               Term_TermType::TABLE.value(),
+                               bob);
+        assert_eq!(from_str::<Value>(&upsert.encode()).unwrap(), from_str::<Value>(&expected).unwrap());
+        let many = users.upsert_many(vec![1, 2]).term.unwrap();
+        assert_eq!(many.get_optargs()[0].get_key(), "conflict");
+    }
+}
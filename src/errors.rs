@@ -79,6 +79,30 @@ pub enum DriverError
     Response(ResponseError),
     Json(JsonError),
     Protobuf(ProtobufError),
+    /// The query did not respond within the time it was given
+    #[error(msg_embedded, non_std, no_from)]
+    Timeout(String),
+    /// A command that talks to the server was not given a connection
+    #[error(msg_embedded, non_std, no_from)]
+    MissingConnection(String),
+    /// `connect` was not given a handle to a Tokio reactor
+    #[error(msg_embedded, non_std, no_from)]
+    MissingHandle(String),
+    /// The connection was closed
+    #[error(msg_embedded, non_std, no_from)]
+    ConnectionClosed(String),
+    /// The server closed the session before sending a full response
+    #[error(msg_embedded, non_std, no_from)]
+    ServerClosed(String),
+    /// No session became available in the pool in time
+    #[error(msg_embedded, non_std, no_from)]
+    PoolExhausted(String),
+    /// `connect` was asked for TLS, which this driver doesn't support
+    #[error(msg_embedded, non_std, no_from)]
+    Tls(String),
+    /// A response could not be deserialized into the type asked for
+    #[error(msg_embedded, non_std, no_from)]
+    Deserialize(String),
     #[error(msg_embedded, non_std, no_from)]
     Other(String),
 }
@@ -7,6 +7,7 @@ extern crate derive_error;
 extern crate futures;
 #[macro_use]
 extern crate lazy_static;
+extern crate net2;
 extern crate ordermap;
 extern crate parking_lot;
 #[macro_use]
@@ -22,6 +23,7 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_path_to_error;
 #[macro_use]
 extern crate slog;
 extern crate tokio_core;
@@ -34,25 +36,43 @@ pub mod errors;
 mod types;
 
 use errors::Error;
+use futures::sink::Wait;
 use futures::sync::mpsc::{Receiver, Sender};
+use futures::sync::oneshot;
 use ordermap::OrderMap;
+use parking_lot::{Condvar, Mutex, RwLock};
+use reql_types::Change;
 #[doc(hidden)]
 pub use protobuf::repeated::RepeatedField;
 #[doc(hidden)]
 pub use ql2::proto::{Datum, Datum_DatumType as DT, Term, Term_TermType as TT};
 #[doc(hidden)]
 pub use reql_derive::*;
+use serde::Serialize;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use slog::Logger;
 
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::net::TcpStream;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use tokio_core::reactor::Remote;
 use uuid::Uuid;
 
+// Parameters of nested functions must not share ids, otherwise
+// the inner function's parameters would shadow the outer ones
+static VAR_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[doc(hidden)]
+pub fn var_id() -> usize
+{
+    VAR_ID.fetch_add(1, Ordering::SeqCst) + 1
+}
+
 /// The result of any command that can potentially return an error
 pub type Result<T> = ::std::result::Result<T, Error>;
 
@@ -64,6 +84,8 @@ pub struct Arg
     term: Result<Term>,
     pool: Option<Connection>,
     remote: Option<Remote>,
+    metrics: Option<Arc<Metrics>>,
+    listener: Option<Arc<ConnectionListener>>,
 }
 
 /// ReQL Response
@@ -74,25 +96,116 @@ pub struct Response<T: DeserializeOwned + Send>
 {
     done: bool,
     rx: Receiver<Result<Option<Document<T>>>>,
+    // Set once the server sends the profile of a query run with `profile: true`
+    profile: Arc<RwLock<Option<Value>>>,
+    // Holds the results instead of the channel unless `overflow` is `block`
+    backlog: Option<Arc<Backlog<T>>>,
+}
+
+// Results waiting to be consumed, for queries run with an `overflow` policy
+// other than `block`. The channel then only wakes the response up.
+#[derive(Debug)]
+struct Backlog<T: DeserializeOwned + Send>
+{
+    docs: Mutex<VecDeque<Result<Option<Document<T>>>>>,
+    size: usize,
+    overflow: Overflow,
+    dropped: AtomicUsize,
+}
+
+// What to do with new results when `buffer_size` of them are waiting
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Overflow
+{
+    Block,
+    DropOldest,
+    Error,
+}
+
+/// A changefeed that survives connection failures
+///
+/// Returned by `run_resilient()`. Whenever the underlying connection fails,
+/// the query is transparently submitted again and a `FeedItem::Gap` is
+/// yielded to mark the point where changes may have been missed.
+pub struct ResilientFeed<T: DeserializeOwned + Send>
+{
+    client: Client,
+    arg: Arg,
+    response: Response<T>,
+}
+
+/// The top documents of an ordered query, kept up to date
+///
+/// Returned by `top_n_feed()`. Each item is the whole window, in order.
+#[must_use = "streams do nothing unless polled"]
+pub struct TopN<T: DeserializeOwned + Send>
+{
+    feed: ResilientFeed<Change<T, T>>,
+    rows: Vec<T>,
+    ready: bool,
+}
+
+/// A response with a function applied to each of its documents
+///
+/// Returned by `Response::map_ok()`.
+#[must_use = "streams do nothing unless polled"]
+pub struct MapOk<T: DeserializeOwned + Send, F>
+{
+    response: Response<T>,
+    f: F,
+}
+
+/// A response with only the documents matching a predicate
+///
+/// Returned by `Response::filter_ok()`.
+#[must_use = "streams do nothing unless polled"]
+pub struct FilterOk<T: DeserializeOwned + Send, F>
+{
+    response: Response<T>,
+    f: F,
 }
 
 struct Request<T: DeserializeOwned + Send>
 {
+    query: String,
     term: Term,
     opts: Term,
     pool: r2d2::Pool<SessionManager>,
     cfg: Config,
-    tx: Sender<Result<Option<Document<T>>>>,
+    tx: Wait<Sender<Result<Option<Document<T>>>>>,
     write: bool,
     retry: bool,
+    failed: bool,
+    // Whether the server has responded to the query yet
+    received: bool,
+    // Whether the caller has dropped the response
+    dropped: bool,
+    deadline: Option<Instant>,
+    checkout_timeout: Option<Duration>,
+    // How long a changefeed can go without results before the session is checked
+    idle_timeout: Option<Duration>,
+    // The token the server uses to tell us which query a response is for
+    token: u64,
+    profile: Arc<RwLock<Option<Value>>>,
+    backlog: Option<Arc<Backlog<T>>>,
+    id: Uuid,
+    running: RunningQuery,
+    conn: Connection,
+    // The server of the session the query is running on
+    server: Option<String>,
     logger: Logger,
 }
 
 struct Session
 {
-    id: u64,
+    // The token of the last query started on this session
+    token: u64,
+    // The name of the server this session is connected to
+    server: String,
     broken: bool,
     stream: TcpStream,
+    // The part of a response read so far
+    pending: Vec<u8>,
     logger: Logger,
 }
 
@@ -101,10 +214,43 @@ struct Config
 {
     cluster: OrderMap<String, Server>,
     opts: Opts,
+    indexes: OrderMap<String, Vec<String>>,
+    metrics: Arc<Metrics>,
+    listener: Arc<ConnectionListener>,
     remote: Remote,
+    // Keeps the reactor started by `connect_sync` running until
+    // the connection is closed
+    reactor: Option<Arc<oneshot::Sender<()>>>,
+    // The version reported by the server during the last handshake
+    server_version: Option<String>,
+    // Set by `drain` so new queries are refused
+    draining: bool,
+    queries: Arc<Queries>,
     logger: Logger,
 }
 
+// The queries running on a connection, so `drain` can wait for them
+struct Queries
+{
+    running: Mutex<OrderMap<Uuid, RunningQuery>>,
+    // Notified every time a query finishes
+    done: Condvar,
+}
+
+// The tokens of the queries running on a session and a clone of its stream,
+// once there is one. It's locked while writing to the session so `drain` can
+// send a `STOP` without mixing it up with the queries' own writes.
+type RunningQuery = Arc<Mutex<Option<(Vec<u64>, TcpStream)>>>;
+
+// The queries `run_many` and `insert_batched` run straight on a checked out
+// session, registered for as long as the session is in use
+struct SessionQueries
+{
+    queries: Arc<Queries>,
+    id: Uuid,
+    running: RunningQuery,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct SessionManager(Connection);
 
@@ -115,28 +261,175 @@ struct SessionManager(Connection);
 /// themselves. Instead it is simply a reference to the
 /// actual underlying connection pool. As such, you can
 /// `clone` or `copy` it.
+///
+/// Keep in mind that all copies refer to the same pool so
+/// calling `close` on any of them closes the pool for all
+/// of them. If different parts of your program need to share
+/// a connection and you want it closed only once they are
+/// all done with it, use a `SharedConnection` instead.
+///
+/// # Driver options
+///
+/// In addition to `db`, `user` and `password`, `connect` accepts the
+/// following options:
+///
+/// - `servers`: the servers to connect to (default `["localhost"]`).
+/// - `discover_hosts`: watch the `rethinkdb.server_status` table and connect
+/// to any server that joins the cluster (default `true`). Set it to `false`
+/// when connecting through a `rethinkdb proxy` whose backend servers are not
+/// reachable from the client. Only the `servers` given are then used.
+/// - `watch_servers`: keep the `rethinkdb.server_status` changefeed open to
+/// follow servers joining and leaving the cluster (default `true`). When
+/// `false` the table is read once while connecting and the latency of the
+/// servers is measured only then. If the user is not allowed to read it,
+/// the `servers` given are used instead. Listeners are not told about
+/// changes in connectivity without the changefeed.
+/// - `ensure_db`: create the `db` given to `connect` if it doesn't exist yet
+/// (default `false`). This is meant for development and tests, where a
+/// fresh server doesn't have the database the program expects.
+/// - `safe_mode`: refuse to run `delete`, `update` or `replace` directly
+/// on a whole `table` (default `false`), so a forgotten `filter` can't wipe
+/// or rewrite every document. Pass `allow_full_table_write: true` to `run`
+/// when such a write is intended.
+/// - `eager`: open the pool's idle sessions, including the handshake, before
+/// `connect` returns (default `false`). This way the first queries don't
+/// have to wait for new sessions and `connect` fails right away if no
+/// session can be established, for example when the servers are
+/// unreachable or the credentials are wrong.
+/// - `reproducible`: make queries return the same results every time they
+/// run (default `false`). Calls to `r.uuid()` without a name are given one
+/// derived from the query, so the same query always generates the same
+/// UUIDs. Queries using `r.now()` or `r.random()` are rejected since those
+/// can't be made deterministic.
+/// - `keepalive`: the number of seconds a session can be idle before TCP
+/// keep-alive probes are sent to check that the server is still there
+/// (default `60`). Set it to `0` to disable keep-alive.
+/// - `tls`: not supported. Rather than connecting without it, `connect`
+/// returns a `DriverError::Tls`, so encrypt the connection with a proxy
+/// such as an SSL tunnel instead.
+/// - `metrics`: not an option as such but an `Arc` of a type implementing
+/// `Metrics`, passed alongside the other arguments. Its hooks are called as
+/// queries run and sessions are created.
+/// - `listener`: like `metrics`, an `Arc<ConnectionListener>` passed
+/// alongside the other arguments. It's told about changes in connectivity.
+/// - `identifier_format`: whether system tables and commands like `config`,
+/// `status` and `rebalance` should identify databases, tables and servers
+/// by `"name"` or by `"uuid"` (default `"name"`). This is used as the
+/// default for every query run through the connection. Pass it to `run` to
+/// override it for a single query.
+/// - `query_timeout`: the number of milliseconds to wait for the first
+/// response of a query before giving up on it (default none). This works
+/// like `Client::run_timeout`, which overrides it for a single query, but applies to
+/// every query run through the connection.
+/// - `checkout_timeout`: the number of milliseconds a query waits for a
+/// session from the connection pool (default `3000`). If none becomes
+/// available in time the query returns a `DriverError::PoolExhausted`, so a
+/// busy pool makes queries fail fast rather than hang. Pass it to `run` to
+/// override it for a single query.
+/// - `validate_indexes`: check that the index used by `between`, `get_all`,
+/// `order_by`, `min` and `max` exists before running the query (default
+/// `false`). The indexes
+/// of each table are cached so this only costs a round trip the first time a
+/// table is used, or when an index is not found.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Connection(Uuid);
 
+/// A reference counted connection
+///
+/// Unlike copies of a `Connection`, which all share the same pool no
+/// matter what, the pool behind a `SharedConnection` is closed only
+/// when the last clone of it is dropped. Pass it to `run` by reference.
+#[derive(Debug, Clone)]
+pub struct SharedConnection(Arc<ConnectionHandle>);
+
+#[derive(Debug)]
+struct ConnectionHandle(Connection);
+
 #[derive(Debug, Clone, Eq)]
 struct Server
 {
     name: String,
     addresses: Vec<SocketAddr>,
     latency: Duration,
+    // Queries that failed in a row because of this server
+    failures: u32,
+    // No new sessions are opened to it until then
+    open_until: Option<Instant>,
+}
+
+/// A snapshot of a server in the cluster
+///
+/// Returned by `Connection::cluster`. The latency is the time it took to
+/// open a TCP connection to the server the last time it was measured.
+#[derive(Debug, Clone)]
+pub struct ServerView
+{
+    pub name: String,
+    pub addresses: Vec<SocketAddr>,
+    pub latency: Duration,
+    pub reachable: bool,
+}
+
+/// A snapshot of a connection pool
+///
+/// Returned by `Connection::pool_state`. A pool whose sessions are
+/// rarely idle is close to running out of them.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolState
+{
+    /// The number of sessions opened by the pool
+    pub connections: u32,
+    /// The number of those sessions not currently in use
+    pub idle_connections: u32,
 }
 
 #[derive(Debug, Clone)]
 struct Opts
 {
     db: String,
+    servers: Vec<String>,
     user: String,
     password: String,
     retries: u64,
     reproducible: bool,
+    validate_indexes: bool,
+    discover_hosts: bool,
+    watch_servers: bool,
+    eager: bool,
+    identifier_format: Option<String>,
+    query_timeout: Option<Duration>,
+    checkout_timeout: Duration,
+    keepalive: Option<Duration>,
+    ensure_db: bool,
+    safe_mode: bool,
     tls: Option<TlsCfg>,
 }
 
+// Options that only affect how the driver runs a query
+// so they are not sent to the server
+#[derive(Debug, Clone)]
+struct RunOpts
+{
+    buffer_size: usize,
+    checkout_timeout: Option<Duration>,
+    overflow: Overflow,
+    allow_full_table_write: bool,
+    idle_timeout: Option<Duration>,
+}
+
+// A query's connection and options, checked and ready to be submitted
+struct Prepared
+{
+    conn: Connection,
+    pool: r2d2::Pool<SessionManager>,
+    cfg: Config,
+    // The global options sent to the server
+    opts: Term,
+    run_opts: RunOpts,
+    // The default database of the query
+    db: String,
+}
+
 #[derive(Debug, Clone)]
 struct TlsCfg
 {
@@ -152,6 +445,7 @@ pub struct Client
     query: String,
     write: bool,
     logger: Logger,
+    run_opts: Result<Term>,
 }
 
 /// The JSON document returned by the server
@@ -162,6 +456,17 @@ pub enum Document<T: DeserializeOwned + Send>
     Unexpected(Value),
 }
 
+/// An item of a `ResilientFeed`
+#[derive(Debug, Clone)]
+pub enum FeedItem<T: DeserializeOwned + Send>
+{
+    /// A result of the changefeed, like the items of a `Response`
+    Doc(Option<Document<T>>),
+    /// The feed was restarted after a connection failure so some
+    /// changes may have been missed
+    Gap,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ReqlResponse
 {
@@ -180,9 +485,103 @@ pub trait IntoArg
     fn into_arg(self) -> Arg;
 }
 
+/// A value of any type serde can serialize
+///
+/// Wrap your own types in it to pass them to commands, for example
+/// `r.table("posts").insert(Json(post))` or `r.expr(Json(settings))`. The
+/// value is sent as a plain ReQL datum, so it can't hold commands. `None`
+/// fields are sent as `null`.
+#[derive(Debug, Clone)]
+pub struct Json<T: Serialize>(pub T);
+
+/// Hooks for collecting metrics about the driver
+///
+/// Pass an `Arc` of your implementation to `connect` to have these called
+/// as the driver works. Every method does nothing by default so you only
+/// need to implement the ones you're interested in.
+pub trait Metrics: Send + Sync
+{
+    /// A query was handed over to be submitted to the server
+    fn query_submitted(&self) {}
+
+    /// A query finished, either successfully or with an error
+    fn query_completed(&self, _latency: Duration, _success: bool) {}
+
+    /// A session was taken from the connection pool
+    fn pool_checkout(&self, _wait: Duration) {}
+
+    /// A new session to the server was established
+    fn session_created(&self) {}
+}
+
+/// Hooks for following changes in connectivity
+///
+/// Pass an `Arc<ConnectionListener>` of your implementation to `connect` to
+/// be told when servers join or leave the cluster and when the driver loses
+/// or regains contact with all of them. These are only called while
+/// `discover_hosts` is enabled. Every method does nothing by default so you
+/// only need to implement the ones you're interested in.
+pub trait ConnectionListener: Send + Sync
+{
+    /// A server joined the cluster
+    fn on_server_up(&self, _name: &str) {}
+
+    /// A server left the cluster
+    fn on_server_down(&self, _name: &str) {}
+
+    /// None of the servers can be reached any more
+    fn on_all_down(&self) {}
+
+    /// A server can be reached again after all of them were down
+    fn on_recovered(&self) {}
+}
+
 /// Lazily execute a command
 pub trait Run<A: IntoArg>
 {
     /// Prepare a commmand to be submitted
+    ///
+    /// Besides the global options understood by the server, `run` accepts the
+    /// following options which only affect the driver:
+    ///
+    /// - `buffer_size`: how many results to hold on to before the driver stops
+    /// reading from the server (default `1024`). When the buffer is full the
+    /// driver waits for you to consume some results, so a lower value keeps
+    /// memory in check for slowly consumed changefeeds.
+    /// - `checkout_timeout`: the number of milliseconds to wait for a session
+    /// from the connection pool, overriding the one given to `connect`. If
+    /// none becomes available in time the response returns a
+    /// `DriverError::PoolExhausted`, so a latency-sensitive query can fail
+    /// fast while the others keep waiting.
+    /// - `overflow`: what to do when `buffer_size` results are waiting to be
+    /// consumed (default `"block"`). With `"drop_oldest"` the oldest of them is
+    /// discarded to make room, which suits monitoring changefeeds where
+    /// falling behind is acceptable, and `Response::dropped` counts the
+    /// discarded results. With `"error"` the query is stopped and the response
+    /// returns an error instead. Pair it with the server's
+    /// `changefeed_queue_size` to bound the changes buffered on the server too.
+    /// - `allow_full_table_write`: run a `delete`, `update` or `replace` on a
+    /// whole table even though the connection is in `safe_mode` (default
+    /// `false`).
+    /// - `idle_timeout`: the number of milliseconds a query like a changefeed
+    /// can go without new results before the driver checks that the server is
+    /// still there (default none). If the server doesn't answer within another
+    /// `idle_timeout` the response returns a `DriverError::ServerClosed`, so a dead
+    /// connection is told apart from a quiet feed and `run_resilient` restarts
+    /// the feed.
+    ///
+    /// Global options are checked before being sent where the driver can catch
+    /// a mistake earlier, like an `array_limit` that isn't a positive integer.
+    /// Raise `array_limit` for a single query that builds arrays larger than
+    /// the default of 100,000 elements, for example
+    /// `r.range().with_args(200000).coerce_to("array").run(args!(conn, {array_limit: 300000}))`.
+    ///
+    /// Options set with `Client::with_default_run_opts` are added to the ones
+    /// given here unless `run` is passed the same option.
+    ///
+    /// The database a table belongs to is, in order of precedence, the one
+    /// given by `r.db()` in the query, the `db` option passed to `run`, the one
+    /// set by `Client::use_db` or the `db` given to `connect`, which defaults
+    /// to `test`.
     fn run<T: DeserializeOwned + Send + 'static>(&self, args: A) -> Result<Response<T>>;
 }
@@ -1,13 +1,13 @@
 #[doc(hidden)]
 #[macro_export]
 macro_rules! var {
-    ($idx:expr) => {{
+    () => {{
         use $crate::{Client, RepeatedField, Term, Datum, TT, DT};
 
         // ID
         let mut id = Datum::new();
         id.set_field_type(DT::R_NUM);
-        id.set_r_num($idx as f64);
+        id.set_r_num($crate::var_id() as f64);
         // DATUM
         let mut datum = Term::new();
         datum.set_field_type(TT::DATUM);
@@ -27,14 +27,16 @@ macro_rules! var {
 #[macro_export]
 macro_rules! func {
     ($f:tt, $( $v:expr ),* $(,)* ) => {{
-        use $crate::{Client, RepeatedField, Term, Datum, TT, DT};
+        use $crate::{Client, IntoArg, RepeatedField, Term, Datum, TT, DT};
 
         // IDs
         // when closure has no args ids doesn't need to be mutable
         // this lint is ignored (see https://github.com/rust-lang/rust/issues/40491)
         #[allow(unused_mut)]
         let mut ids = Vec::new();
-        let res: Client = $f(
+        // The body can return a command or, like `|row| args!({...})`,
+        // anything else that can be turned into an argument
+        let res = $f(
             $({
                 let id = $v;
                 for t in id.term().unwrap().get_args() {
@@ -42,7 +44,7 @@ macro_rules! func {
                 }
                 id
             },)*
-        );
+        ).into_arg();
         let mut closure = Client::new();
         match res.term() {
             Ok(res) => {
@@ -113,6 +115,21 @@ proc_macro_expr_decl!(
 /// r.branch(args!(r.expr(x).gt(5), "big", "small"));
 /// # }
 /// ```
+///
+/// Optional arguments go in an object at the end. For example, `filter`
+/// normally raises an error on documents missing the fields its predicate
+/// uses. Set `default` to `false` to skip those documents instead, to `true`
+/// to include them or to `r.error(args!())` to keep raising the error.
+///
+/// ```
+/// # #![allow(unused_must_use)]
+/// # #[macro_use] extern crate reql;
+/// # use reql::Client;
+/// # fn main() {
+/// # let r = Client::new();
+/// r.table("users").filter(args!(|user| user.get_field("active"), {default: false}));
+/// # }
+/// ```
 #[macro_export]
 macro_rules! args {
     ( $($args:tt)* ) => {{
@@ -1,13 +1,12 @@
 //! The ReQL data types
 
-use {Request, Result};
+use Result;
 use errors::DriverError;
 use protobuf::ProtobufEnum;
 use protobuf::repeated::RepeatedField;
 use ql2::proto::{Datum, Datum_AssocPair as DatumPair, Datum_DatumType as DatumType, Term,
                  Term_AssocPair as TermPair, Term_TermType as TermType};
 use serde::Serialize;
-use serde::de::DeserializeOwned;
 use serde_json::value::{Value, to_value};
 
 pub trait FromJson
@@ -111,7 +110,8 @@ impl Encode for Datum
             DatumType::R_NULL => String::from("null"),
             DatumType::R_BOOL => format!("{}", self.get_r_bool()),
             DatumType::R_NUM => format!("{}", self.get_r_num()),
-            DatumType::R_STR => format!("\"{}\"", self.get_r_str()),
+            // Quotes and other special characters need escaping
+            DatumType::R_STR => Value::String(self.get_r_str().to_string()).to_string(),
             DatumType::R_ARRAY => {
                 let mut args = format!("[{},[", TermType::MAKE_ARRAY.value());
                 for term in self.get_r_array() {
@@ -137,53 +137,53 @@ impl Encode for Datum
     }
 }
 
-impl<T: DeserializeOwned + Send> Request<T>
+impl Encode for Term
 {
-    pub fn encode(&mut self, data: &Term, encoding_opts: bool) -> String
+    fn encode(&self) -> String
     {
         let mut res = Vec::new();
-        if !data.is_datum() {
-            res.push(format!("[{}", data.get_field_type().value()));
+        if !self.is_datum() {
+            res.push(format!("[{}", self.get_field_type().value()));
         }
-        if data.has_datum() {
-            let datum = data.get_datum();
+        if self.has_datum() {
+            let datum = self.get_datum();
             res.push(datum.encode());
         }
-        let terms = data.get_args();
+        let terms = self.get_args();
         if !terms.is_empty() {
-            let mut args = if data.has_field_type() {
+            let mut args = if self.has_field_type() {
                 String::from("[")
             } else {
                 String::new()
             };
             for term in terms {
-                args.push_str(&format!("{},", self.encode(&term, encoding_opts)));
+                args.push_str(&format!("{},", term.encode()));
             }
             args = args.trim_right_matches(",").to_string();
-            if data.has_field_type() {
+            if self.has_field_type() {
                 args.push_str("]");
             }
             res.push(args);
         }
-        let opts = data.clone().take_optargs().into_vec();
+        let opts = self.get_optargs();
         if !opts.is_empty() {
-            res.push(format!("{}", self.encode_pairs(&opts, encoding_opts)));
+            res.push(encode_pairs(opts));
         }
         let mut res = res.join(",");
-        if !data.is_datum() {
+        if !self.is_datum() {
             res.push_str("]");
         }
         res
     }
+}
 
-    fn encode_pairs(&mut self, data: &Vec<TermPair>, encoding_opts: bool) -> String
-    {
-        let mut opts = String::from("{");
-        for term in data {
-            opts.push_str(&format!("\"{}\":{},", term.get_key(), self.encode(term.get_val(), encoding_opts)));
-        }
-        opts = opts.trim_right_matches(",").to_string();
-        opts.push_str("}");
-        opts
+fn encode_pairs(data: &[TermPair]) -> String
+{
+    let mut opts = String::from("{");
+    for term in data {
+        opts.push_str(&format!("\"{}\":{},", term.get_key(), term.get_val().encode()));
     }
+    opts = opts.trim_right_matches(",").to_string();
+    opts.push_str("}");
+    opts
 }
@@ -0,0 +1,2006 @@
+//! Tests that need a RethinkDB server listening on localhost
+//!
+//! They are ignored by default. Run them with `cargo test -- --ignored`.
+
+extern crate futures;
+#[macro_use]
+extern crate reql;
+extern crate reql_types;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate tokio_core;
+
+use futures::Stream;
+use reql::{Arg, Client, Connection, ConnectionListener, DT, Datum, Document, FeedItem, Json, Metrics, Run,
+           SharedConnection, TT, Term};
+use reql::errors::{DriverError, Error, RuntimeError};
+use reql_types::{Change, DateTime, Join, Nearest, ObjectInfo, WriteResult};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Core;
+
+fn connect(r: &Client) -> Connection
+{
+    r.connect_sync(args!()).unwrap()
+}
+
+// Create a table in the `test` database for a single test
+fn create_table(r: &Client, conn: Connection, name: &str) -> Client
+{
+    r.db("test").table_create(name).run::<Value>(conn).unwrap().single().unwrap();
+    r.db("test").table(name)
+}
+
+fn drop_table(r: &Client, conn: Connection, name: &str)
+{
+    r.db("test").table_drop(name).run::<Value>(conn).unwrap().single().unwrap();
+}
+
+#[test]
+#[ignore]
+fn into_map_collects_key_value_pairs()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let scores = r.expr(Json(vec![("alice", 3), ("bob", 5)]))
+        .run::<Value>(conn)
+        .unwrap()
+        .into_map::<String, u64>()
+        .unwrap();
+    assert_eq!(scores.len(), 2);
+    assert_eq!(scores["alice"], 3);
+    assert_eq!(scores["bob"], 5);
+}
+
+#[test]
+#[ignore]
+fn nth_and_slice_count_from_the_end()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let values = r.expr(Json(vec![1, 2, 3, 4, 5]));
+    let last = values.nth(-1).run::<i64>(conn).unwrap().single().unwrap();
+    assert_eq!(last, Some(5));
+    let tail = values.slice(-3).run::<Vec<i64>>(conn).unwrap().single().unwrap();
+    assert_eq!(tail, Some(vec![3, 4, 5]));
+}
+
+#[test]
+#[ignore]
+fn do_binds_values_for_the_function()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let square = r.do_(args!(r.expr(5), |x| x.clone().mul(x)))
+        .run::<i64>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    assert_eq!(square, Some(25));
+}
+
+#[test]
+#[ignore]
+fn lt_compares_every_value()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let increasing = r.lt(args!(1, 2, 3)).run::<bool>(conn).unwrap().single().unwrap();
+    assert_eq!(increasing, Some(true));
+    let unordered = r.lt(args!(1, 3, 2)).run::<bool>(conn).unwrap().single().unwrap();
+    assert_eq!(unordered, Some(false));
+}
+
+#[test]
+#[ignore]
+fn update_with_a_literal_removes_the_field()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let table = r.db("test").table("reql_literal");
+    r.db("test").table_create("reql_literal").run::<Value>(conn).unwrap().single().unwrap();
+    table.insert(args!({id: 1, temp_field: "scratch"})).run::<Value>(conn).unwrap().single().unwrap();
+    table.get(1).update(args!({temp_field: r.literal()})).run::<Value>(conn).unwrap().single().unwrap();
+    let has_field = table.get(1).has_fields("temp_field").run::<bool>(conn).unwrap().single().unwrap();
+    r.db("test").table_drop("reql_literal").run::<Value>(conn).unwrap().single().unwrap();
+    assert_eq!(has_field, Some(false));
+}
+
+#[test]
+#[ignore]
+fn r_row_filters_like_a_closure()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = r.expr(Json(vec![("alice", 17), ("bob", 18), ("carol", 40)]))
+        .map(args!(|user| args!({name: user.nth(0), age: user.nth(1)})));
+    let with_row = users.filter(r.row().get_field("age").gt(17))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let with_closure = users.filter(args!(|user| user.get_field("age").gt(17)))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    assert_eq!(with_row, with_closure);
+    assert_eq!(with_row.unwrap().as_array().unwrap().len(), 2);
+}
+
+// Serve `/pages/1` to `/pages/3`, each linking to the next one in its body
+fn serve_pages(listener: TcpListener)
+{
+    let port = listener.local_addr().unwrap().port();
+    for stream in listener.incoming().take(3) {
+        let mut stream = stream.unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map(Result::unwrap);
+        // Like `GET /pages/1 HTTP/1.1`
+        let request = lines.next().unwrap();
+        // Read the headers so closing the stream doesn't reset it
+        for line in &mut lines {
+            if line.is_empty() {
+                break;
+            }
+        }
+        let page: u64 = request.split('/').nth(2).unwrap().split(' ').next().unwrap().parse().unwrap();
+        let next = if page < 3 {
+            format!(r#""http://127.0.0.1:{}/pages/{}""#, port, page + 1)
+        } else {
+            String::from("null")
+        };
+        let body = format!(r#"{{"items": [{}, {}], "next": {}}}"#, page * 2 - 1, page * 2, next);
+        write!(stream,
+               "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               body.len(),
+               body)
+                .unwrap();
+    }
+}
+
+#[test]
+#[ignore]
+fn http_follows_a_page_function_through_every_page()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://127.0.0.1:{}/pages/1", listener.local_addr().unwrap().port());
+    let server = thread::spawn(move || serve_pages(listener));
+    let items = r.http(args!(url.as_str(), {page: |info| info.get_field("body").get_field("next"), page_limit: 5}))
+        .concat_map(args!(|page| page.get_field("items")))
+        .run::<u64>(conn)
+        .unwrap()
+        .collect_capped(10)
+        .unwrap();
+    server.join().unwrap();
+    assert_eq!(items, vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+#[ignore]
+fn drain_stops_queries_still_running_after_the_timeout()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    // Never finishes on its own
+    let feed = r.db("rethinkdb").table("server_status").changes().run::<Value>(conn).unwrap();
+    let start = Instant::now();
+    conn.drain(Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    // The feed ends once the server has stopped it
+    for _ in feed.wait() {}
+    assert!(r.expr(1).run::<i64>(conn).is_err());
+}
+
+#[test]
+#[ignore]
+fn top_n_feed_matches_a_new_query_as_scores_change()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    r.db("test").table_create("reql_top_n").run::<Value>(conn).unwrap().single().unwrap();
+    let table = r.db("test").table("reql_top_n");
+    table.index_create("score").run::<Value>(conn).unwrap().single().unwrap();
+    table.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    for &(id, score) in &[(1, 10), (2, 20), (3, 30), (4, 40)] {
+        table.insert(args!({id: id, score: score})).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let top = table.order_by(args!({index: r.desc("score")})).limit(3);
+    let query = || top.run::<Vec<Value>>(conn).unwrap().single().unwrap().unwrap();
+    let mut windows = top.top_n_feed::<_, Value>(conn).unwrap().wait();
+    assert_eq!(windows.next().unwrap().unwrap(), query());
+    // Moves into the window, pushing the lowest score out
+    table.get(1).update(args!({score: 50})).run::<Value>(conn).unwrap().single().unwrap();
+    assert_eq!(windows.next().unwrap().unwrap(), query());
+    // Moves within the window
+    table.get(3).update(args!({score: 45})).run::<Value>(conn).unwrap().single().unwrap();
+    assert_eq!(windows.next().unwrap().unwrap(), query());
+    drop(windows);
+    r.db("test").table_drop("reql_top_n").run::<Value>(conn).unwrap().single().unwrap();
+}
+
+#[test]
+#[ignore]
+fn query_timeout_applies_to_queries_without_their_own()
+{
+    let r = Client::new();
+    let conn = r.connect_sync(args!({query_timeout: 200})).unwrap();
+    // Accepts the request but takes too long to answer it
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://127.0.0.1:{}/slow", listener.local_addr().unwrap().port());
+    thread::spawn(move || {
+                      let stream = listener.accept().unwrap();
+                      thread::sleep(Duration::from_secs(5));
+                      drop(stream);
+                  });
+    let start = Instant::now();
+    let error = r.http(url.as_str()).run::<Value>(conn).unwrap().single().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(format!("{:?}", error).contains("Timeout"), "{:?}", error);
+}
+
+#[test]
+#[ignore]
+fn create_if_not_exists_can_be_called_twice()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    assert_eq!(r.db_create_if_not_exists(conn, "reql_twice").unwrap(), true);
+    assert_eq!(r.db_create_if_not_exists(conn, "reql_twice").unwrap(), false);
+    let db = r.db("reql_twice");
+    assert_eq!(db.table_create_if_not_exists(conn, "posts").unwrap(), true);
+    assert_eq!(db.table_create_if_not_exists(conn, "posts").unwrap(), false);
+    r.db_drop("reql_twice").run::<Value>(conn).unwrap().single().unwrap();
+}
+
+#[test]
+#[ignore]
+fn one_client_queries_tables_in_two_dbs()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    for name in &["reql_left", "reql_right"] {
+        r.db_create(*name).run::<Value>(conn).unwrap().single().unwrap();
+        r.db(*name).table_create("places").run::<Value>(conn).unwrap().single().unwrap();
+        r.db(*name).table("places").insert(args!({id: 1, db: *name})).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let left = r.use_db("reql_left").with_default_run_opts(args!({read_mode: "outdated"}));
+    let right = left.db("reql_right");
+    let db_of = |doc: Client| doc.get_field("db").run::<String>(conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(db_of(left.table("places").get(1)), "reql_left");
+    assert_eq!(db_of(right.table("places").get(1)), "reql_right");
+    assert_eq!(db_of(left.table("places").get(1)), "reql_left");
+    for name in &["reql_left", "reql_right"] {
+        r.db_drop(*name).run::<Value>(conn).unwrap().single().unwrap();
+    }
+}
+
+// Forwards connections to the server so a test can cut them
+struct Proxy
+{
+    addr: String,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    frozen: Arc<AtomicBool>,
+}
+
+impl Proxy
+{
+    fn start() -> Proxy
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        let frozen = Arc::new(AtomicBool::new(false));
+        let paused = frozen.clone();
+        thread::spawn(move || for client in listener.incoming() {
+                          let client = client.unwrap();
+                          let server = TcpStream::connect("localhost:28015").unwrap();
+                          accepted.lock().unwrap().push(client.try_clone().unwrap());
+                          forward(client.try_clone().unwrap(), server.try_clone().unwrap(), paused.clone());
+                          forward(server, client, paused.clone());
+                      });
+        Proxy {
+            addr: addr,
+            clients: clients,
+            frozen: frozen,
+        }
+    }
+
+    // Stop passing anything on while keeping the connections open, like
+    // a server that went away without closing them
+    fn freeze(&self)
+    {
+        self.frozen.store(true, Ordering::SeqCst);
+    }
+
+    // Close every connection opened through the proxy so far
+    fn cut(&self)
+    {
+        for client in self.clients.lock().unwrap().drain(..) {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn forward(mut from: TcpStream, mut to: TcpStream, frozen: Arc<AtomicBool>)
+{
+    thread::spawn(move || {
+                      let mut buf = [0; 4096];
+                      loop {
+                          let n = match from.read(&mut buf) {
+                              Ok(0) | Err(_) => break,
+                              Ok(n) => n,
+                          };
+                          // Frozen connections swallow what's sent through them
+                          if !frozen.load(Ordering::SeqCst) && to.write_all(&buf[..n]).is_err() {
+                              break;
+                          }
+                      }
+                      let _ = to.shutdown(Shutdown::Both);
+                  });
+}
+
+#[test]
+#[ignore]
+fn resilient_feed_resumes_after_the_connection_drops()
+{
+    let r = Client::new();
+    let setup = connect(&r);
+    r.db("test").table_create("reql_resilient").run::<Value>(setup).unwrap().single().unwrap();
+    let table = r.db("test").table("reql_resilient");
+    let proxy = Proxy::start();
+    let conn = r.connect_sync(args!({servers: [proxy.addr.as_str()], discover_hosts: false})).unwrap();
+    let mut feed = table.changes().run_resilient::<_, Value>(conn).unwrap().wait();
+    let new_id = |item: FeedItem<Value>| match item {
+        FeedItem::Doc(Some(Document::Expected(change))) => change["new_val"]["id"].clone(),
+        item => panic!("expected a change, got {:?}", item),
+    };
+    // Give the server time to open the feed before changing the table
+    thread::sleep(Duration::from_millis(500));
+    table.insert(args!({id: 1})).run::<Value>(setup).unwrap().single().unwrap();
+    assert_eq!(new_id(feed.next().unwrap().unwrap()), 1);
+    proxy.cut();
+    match feed.next().unwrap().unwrap() {
+        FeedItem::Gap => {}
+        item => panic!("expected a gap, got {:?}", item),
+    }
+    thread::sleep(Duration::from_millis(500));
+    table.insert(args!({id: 2})).run::<Value>(setup).unwrap().single().unwrap();
+    assert_eq!(new_id(feed.next().unwrap().unwrap()), 2);
+    r.db("test").table_drop("reql_resilient").run::<Value>(setup).unwrap().single().unwrap();
+}
+
+#[test]
+#[ignore]
+fn insert_batched_adds_up_every_batch()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_batched");
+    let docs = (0..5000).map(|id| json!({"id": id, "title": format!("post {}", id)}));
+    let status = posts.insert_batched(conn, docs, 500).unwrap();
+    assert_eq!(status.write.inserted, 5000);
+    assert_eq!(status.write.errors, 0);
+    assert!(!status.synced);
+    let count = posts.count().run::<u64>(conn).unwrap().single().unwrap();
+    assert_eq!(count, Some(5000));
+    drop_table(&r, conn, "reql_batched");
+}
+
+#[test]
+#[ignore]
+fn insert_conflicts_update_or_keep_the_newer_document()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_conflict");
+    posts.insert(args!({id: 1, title: "Hello", updated_at: 20})).run::<Value>(conn).unwrap().single().unwrap();
+    posts.insert(args!({id: 1, draft: false}, {conflict: "update"})).run::<Value>(conn).unwrap().single().unwrap();
+    let post = posts.get(1).run::<Value>(conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(post, json!({"id": 1, "title": "Hello", "updated_at": 20, "draft": false}));
+    for &(title, updated_at) in &[("Stale", 10), ("Fresh", 30)] {
+        let doc = args!({id: 1, title: title, updated_at: updated_at});
+        // Keep whichever version was updated last
+        posts.insert(args!(doc, {conflict: |_id, old, new| {
+                                     let newer = new.get_field("updated_at").gt(old.get_field("updated_at"));
+                                     r.branch(args!(newer, new, old))
+                                 }}))
+            .run::<Value>(conn)
+            .unwrap()
+            .single()
+            .unwrap();
+    }
+    let title = posts.get(1).get_field("title").run::<String>(conn).unwrap().single().unwrap();
+    assert_eq!(title, Some(String::from("Fresh")));
+    drop_table(&r, conn, "reql_conflict");
+}
+
+#[test]
+#[ignore]
+fn run_timeout_stops_a_query_past_its_deadline()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let start = Instant::now();
+    let res = r.js(args!("while(true) {}", {timeout: 30}))
+        .run_timeout::<_, Value>(conn, Duration::from_millis(200))
+        .unwrap()
+        .single();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    match res {
+        Err(Error::Driver(ref error)) => {
+            match **error {
+                DriverError::Timeout(_) => {}
+                ref error => panic!("expected a timeout, got {}", error),
+            }
+        }
+        res => panic!("expected a timeout, got {:?}", res),
+    }
+    // The server is no longer running it
+    thread::sleep(Duration::from_millis(200));
+    let running = r.db("rethinkdb")
+        .table("jobs")
+        .filter(args!(|job| job.get_field("info").get_field("query").default("").match_("while")))
+        .count()
+        .run::<u64>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    assert_eq!(running, Some(0));
+}
+
+#[test]
+#[ignore]
+fn server_errors_include_the_query_that_failed()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let error = r.expr(1).add("a").run::<Value>(conn).unwrap().single().unwrap_err();
+    match error {
+        Error::Runtime(ref error) => {
+            match **error {
+                RuntimeError::QueryLogic(ref msg) => {
+                    assert!(msg.contains("Expected type NUMBER but found STRING"), "{}", msg);
+                    assert!(msg.ends_with(r#".add("a").run(conn)"#), "{}", msg);
+                }
+                ref error => panic!("expected a query logic error, got {:?}", error),
+            }
+        }
+        error => panic!("expected a query logic error, got {:?}", error),
+    }
+}
+
+#[test]
+#[ignore]
+fn reproducible_runs_generate_the_same_uuids()
+{
+    let r = Client::new();
+    let conn = r.connect_sync(args!({reproducible: true})).unwrap();
+    let uuid = || r.uuid().run::<String>(conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(uuid(), uuid());
+    let distinct = r.uuid().ne(r.uuid()).run::<bool>(conn).unwrap().single().unwrap();
+    assert_eq!(distinct, Some(true));
+    assert!(r.now().run::<Value>(conn).is_err());
+}
+
+#[test]
+#[ignore]
+fn validate_indexes_catches_a_missing_index()
+{
+    let r = Client::new();
+    let setup = connect(&r);
+    let posts = create_table(&r, setup, "reql_indexes");
+    posts.index_create("date").run::<Value>(setup).unwrap().single().unwrap();
+    posts.index_wait().run::<Value>(setup).unwrap().single().unwrap();
+    let conn = r.connect_sync(args!({validate_indexes: true})).unwrap();
+    let by_date = posts.order_by(args!({index: r.desc("date")})).run::<Vec<Value>>(conn);
+    assert!(by_date.unwrap().single().is_ok());
+    match posts.between(args!(1, 10, {index: "author"})).run::<Value>(conn) {
+        Err(error) => assert!(format!("{:?}", error).contains("no such index 'author'"), "{:?}", error),
+        Ok(_) => panic!("ran a query on a missing index"),
+    }
+    drop_table(&r, setup, "reql_indexes");
+}
+
+#[test]
+#[ignore]
+fn the_cluster_stays_as_given_without_host_discovery()
+{
+    let r = Client::new();
+    let names = |conn: Connection| -> Vec<String> { conn.cluster().into_iter().map(|server| server.name).collect() };
+    let pinned = r.connect_sync(args!({servers: ["127.0.0.1:28015"], discover_hosts: false})).unwrap();
+    thread::sleep(Duration::from_secs(1));
+    assert_eq!(names(pinned), vec![String::from("127.0.0.1:28015")]);
+    assert_eq!(r.expr(1).run::<i64>(pinned).unwrap().single().unwrap(), Some(1));
+    // With discovery the servers are named after their `server_status`
+    let discovered = r.connect_sync(args!({servers: ["127.0.0.1:28015"]})).unwrap();
+    assert!(!names(discovered).contains(&String::from("127.0.0.1:28015")));
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Post
+{
+    id: u64,
+    title: String,
+}
+
+#[test]
+#[ignore]
+fn single_gets_a_present_or_missing_document()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_single");
+    posts.insert(args!({id: 1, title: "Hello"})).run::<Value>(conn).unwrap().single().unwrap();
+    let present = posts.get(1).run::<Post>(conn).unwrap().single().unwrap();
+    assert_eq!(present,
+               Some(Post {
+                        id: 1,
+                        title: String::from("Hello"),
+                    }));
+    let missing = posts.get(2).run::<Post>(conn).unwrap().single().unwrap();
+    assert_eq!(missing, None);
+    drop_table(&r, conn, "reql_single");
+}
+
+#[test]
+#[ignore]
+fn order_by_a_descending_index_returns_the_newest_first()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_desc");
+    posts.index_create("date").run::<Value>(conn).unwrap().single().unwrap();
+    posts.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    for &(id, date) in &[(1, "2017-01-02"), (2, "2017-03-01"), (3, "2017-02-15")] {
+        posts.insert(args!({id: id, date: date})).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let newest_first = posts.order_by(args!({index: r.desc("date")}))
+        .get_field("id")
+        .run::<Vec<u64>>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    assert_eq!(newest_first, Some(vec![2, 3, 1]));
+    drop_table(&r, conn, "reql_desc");
+}
+
+#[test]
+#[ignore]
+fn random_stays_in_its_range_and_floor_rounds_down()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    for _ in 0..20 {
+        let n = r.random().with_args(args!(1, 10)).run::<i64>(conn).unwrap().single().unwrap().unwrap();
+        assert!(n >= 1 && n < 10, "{} is out of range", n);
+    }
+    let x = r.random().with_args(args!(1, 10, {float: true})).run::<f64>(conn).unwrap().single().unwrap().unwrap();
+    assert!(x >= 1.0 && x < 10.0, "{} is out of range", x);
+    let floor = r.expr(2.7).floor().run::<i64>(conn).unwrap().single().unwrap();
+    assert_eq!(floor, Some(2));
+}
+
+// Records the hooks it's called with
+#[derive(Default)]
+struct Recorded
+{
+    submitted: Mutex<u32>,
+    completed: Mutex<Vec<(Duration, bool)>>,
+}
+
+impl Metrics for Recorded
+{
+    fn query_submitted(&self)
+    {
+        *self.submitted.lock().unwrap() += 1;
+    }
+
+    fn query_completed(&self, latency: Duration, success: bool)
+    {
+        self.completed.lock().unwrap().push((latency, success));
+    }
+}
+
+impl Recorded
+{
+    // Hooks are called once the response has been handed over, so the
+    // query can return before they are
+    fn wait_for_completed(&self, n: usize) -> Vec<(Duration, bool)>
+    {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let completed = self.completed.lock().unwrap().clone();
+            if completed.len() >= n || Instant::now() > deadline {
+                return completed;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn metrics_are_told_how_long_a_query_took()
+{
+    let r = Client::new();
+    let metrics = Arc::new(Recorded::default());
+    let hooks = metrics.clone();
+    let conn = r.connect_sync(args!(hooks)).unwrap();
+    let start = Instant::now();
+    r.expr(1).run::<i64>(conn).unwrap().single().unwrap();
+    let completed = metrics.wait_for_completed(1);
+    let elapsed = start.elapsed();
+    assert_eq!(*metrics.submitted.lock().unwrap(), 1);
+    assert_eq!(completed.len(), 1);
+    let (latency, success) = completed[0];
+    assert!(success);
+    assert!(latency > Duration::new(0, 0) && latency <= elapsed,
+            "{:?} doesn't fit in the {:?} the query took",
+            latency,
+            elapsed);
+    assert!(r.expr(1).add("a").run::<i64>(conn).unwrap().single().is_err());
+    let completed = metrics.wait_for_completed(2);
+    assert_eq!(completed.last().map(|&(_, success)| success), Some(false));
+}
+
+#[test]
+#[ignore]
+fn a_small_buffer_holds_the_query_back_until_it_is_read()
+{
+    let r = Client::new();
+    let metrics = Arc::new(Recorded::default());
+    let hooks = metrics.clone();
+    let conn = r.connect_sync(args!(hooks)).unwrap();
+    let mut numbers = r.range().with_args(100).run::<u64>(args!(conn, {buffer_size: 2})).unwrap().wait();
+    assert!(numbers.next().unwrap().unwrap().is_some());
+    // The rest of the batch doesn't fit so the query can't finish yet
+    thread::sleep(Duration::from_millis(200));
+    assert!(metrics.completed.lock().unwrap().is_empty());
+    assert_eq!(numbers.count(), 99);
+    assert_eq!(metrics.wait_for_completed(1).len(), 1);
+}
+
+#[test]
+#[ignore]
+fn into_map_turns_two_columns_into_a_map()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let players = create_table(&r, conn, "reql_into_map");
+    players.insert(Json(vec![json!({"id": 1, "name": "alice", "score": 3, "team": "red"}),
+                             json!({"id": 2, "name": "bob", "score": 5, "team": "blue"})]))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let scores = players.map(args!(|player| vec![player.get_field("name"), player.get_field("score")]))
+        .run::<Value>(conn)
+        .unwrap()
+        .into_map::<String, u64>()
+        .unwrap();
+    assert_eq!(scores.len(), 2);
+    assert_eq!(scores["alice"], 3);
+    assert_eq!(scores["bob"], 5);
+    drop_table(&r, conn, "reql_into_map");
+}
+
+#[test]
+#[ignore]
+fn get_all_takes_keys_built_at_runtime()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_get_all_args");
+    for id in 1..6 {
+        let post = json!({"id": id, "title": format!("post {}", id)});
+        posts.insert(post).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let keys: Vec<u64> = (1..6).filter(|id| id % 2 == 1).collect();
+    let mut found = posts.get_all(r.args(keys)).run::<Post>(conn).unwrap().collect_capped(10).unwrap();
+    found.sort_by_key(|post| post.id);
+    let ids: Vec<u64> = found.iter().map(|post| post.id).collect();
+    assert_eq!(ids, vec![1, 3, 5]);
+    drop_table(&r, conn, "reql_get_all_args");
+}
+
+#[test]
+#[ignore]
+fn fold_emits_a_running_sum()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let sales = create_table(&r, conn, "reql_running_sum");
+    let rows: Vec<Value> = [4, 1, 3, 2].iter().enumerate().map(|(i, n)| json!({"id": i, "amount": n})).collect();
+    sales.insert(Json(rows)).run::<Value>(conn).unwrap().single().unwrap();
+    let totals = sales.order_by("id")
+        .fold(args!(0,
+                    |total, sale| total.add(sale.get_field("amount")),
+                    {emit: |_before, _sale, total| vec![total]}))
+        .run::<u64>(conn)
+        .unwrap()
+        .collect_capped(10)
+        .unwrap();
+    assert_eq!(totals, vec![4, 5, 8, 10]);
+    drop_table(&r, conn, "reql_running_sum");
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct User
+{
+    id: u64,
+    name: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Order
+{
+    id: u64,
+    user_id: u64,
+    total: u64,
+}
+
+// An order zipped with its user
+#[derive(Debug, Deserialize, PartialEq)]
+struct UserOrder
+{
+    id: u64,
+    user_id: u64,
+    total: u64,
+    name: String,
+}
+
+#[test]
+#[ignore]
+fn eq_join_zips_users_and_orders_into_one_struct()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_join_users");
+    let orders = create_table(&r, conn, "reql_join_orders");
+    users.insert(json!({"id": 1, "name": "alice"})).run::<Value>(conn).unwrap().single().unwrap();
+    orders.insert(json!({"id": 10, "user_id": 1, "total": 25})).run::<Value>(conn).unwrap().single().unwrap();
+
+    let joined = orders.eq_join(args!("user_id", users.clone()))
+        .run::<Join<Order, User>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert_eq!(joined.left, Order { id: 10, user_id: 1, total: 25 });
+    assert_eq!(joined.right, User { id: 1, name: String::from("alice") });
+
+    // Both sides have an `id`, the user's wins
+    let zipped = orders.eq_join(args!("user_id", users.clone()))
+        .zip()
+        .run::<UserOrder>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    assert_eq!(zipped,
+               Some(UserOrder {
+                        id: 1,
+                        user_id: 1,
+                        total: 25,
+                        name: String::from("alice"),
+                    }));
+    drop_table(&r, conn, "reql_join_users");
+    drop_table(&r, conn, "reql_join_orders");
+}
+
+#[test]
+#[ignore]
+fn a_shared_connection_is_closed_by_its_last_clone()
+{
+    let r = Client::new();
+    let first: SharedConnection = connect(&r).shared();
+    let second = first.clone();
+    let conn = first.connection();
+    drop(first);
+    let one = r.expr(1).run::<i64>(&second).unwrap().single().unwrap();
+    assert_eq!(one, Some(1));
+    drop(second);
+    assert!(r.expr(1).run::<i64>(conn).is_err());
+}
+
+fn is_uuid(value: &Value) -> bool
+{
+    value.as_str().map_or(false, |s| s.len() == 36 && s.matches('-').count() == 4)
+}
+
+#[test]
+#[ignore]
+fn config_identifies_shard_replicas_by_uuid_when_asked()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let table = create_table(&r, conn, "reql_identifier_format");
+    let by_name = table.config().run::<Value>(conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(by_name["db"], json!("test"));
+    assert!(!is_uuid(&by_name["shards"][0]["primary_replica"]));
+
+    let by_uuid = table.config()
+        .run::<Value>(args!(conn, {identifier_format: "uuid"}))
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert!(is_uuid(&by_uuid["db"]), "{}", by_uuid);
+    assert!(is_uuid(&by_uuid["shards"][0]["primary_replica"]), "{}", by_uuid);
+    assert!(by_uuid["shards"][0]["replicas"].as_array().unwrap().iter().all(is_uuid));
+
+    // The same as a default for the connection
+    let uuid_conn = r.connect_sync(args!({identifier_format: "uuid"})).unwrap();
+    let config = table.config().run::<Value>(uuid_conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(config["db"], by_uuid["db"]);
+    drop_table(&r, conn, "reql_identifier_format");
+}
+
+#[test]
+#[ignore]
+fn run_many_returns_fifty_results_in_order()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let queries = (0..50).map(|i| r.expr(i).mul(2)).collect();
+    let results = r.run_many::<_, i64>(conn, queries).unwrap();
+    assert_eq!(results.len(), 50);
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result.unwrap(), i as i64 * 2);
+    }
+}
+
+#[test]
+#[ignore]
+fn pool_state_counts_the_sessions_in_use()
+{
+    let r = Client::new();
+    let conn = r.connect_sync(args!({eager: true})).unwrap();
+    let table = create_table(&r, conn, "reql_pool_state");
+    let before = conn.pool_state().unwrap();
+    assert!(before.idle_connections > 0);
+    // Changefeeds hold on to their sessions until they are dropped
+    let feeds: Vec<_> = (0..3).map(|_| table.changes().run::<Value>(conn).unwrap()).collect();
+    thread::sleep(Duration::from_millis(200));
+    let during = conn.pool_state().unwrap();
+    assert!(during.connections - during.idle_connections >= 3, "{:?}", during);
+    assert!(during.idle_connections < before.idle_connections || during.connections > before.connections,
+            "{:?} then {:?}",
+            before,
+            during);
+    drop(feeds);
+    drop_table(&r, conn, "reql_pool_state");
+}
+
+#[test]
+#[ignore]
+fn parallel_increments_are_all_counted()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let counters = create_table(&r, conn, "reql_counters");
+    counters.insert(json!({"id": "x", "n": 0})).run::<Value>(conn).unwrap().single().unwrap();
+    let workers: Vec<_> = (0..100)
+        .map(|_| {
+            thread::spawn(move || {
+                let r = Client::new();
+                r.db("test")
+                    .table("reql_counters")
+                    .get("x")
+                    .update(args!(|row| args!({n: row.get_field("n").add(1)})))
+                    .run::<Value>(conn)
+                    .unwrap()
+                    .single()
+                    .unwrap()
+            })
+        })
+        .collect();
+    for worker in workers {
+        let res = worker.join().unwrap().unwrap();
+        assert_eq!(res["replaced"], json!(1));
+    }
+    let n = counters.get("x").get_field("n").run::<u64>(conn).unwrap().single().unwrap();
+    assert_eq!(n, Some(100));
+    drop_table(&r, conn, "reql_counters");
+}
+
+#[test]
+#[ignore]
+fn filter_default_decides_what_to_do_with_missing_fields()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_filter_default");
+    let docs = vec![json!({"id": 1, "active": true}), json!({"id": 2, "active": false}), json!({"id": 3})];
+    users.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap();
+    let ids = |users: Client| {
+        let mut ids = users.get_field("id").run::<u64>(conn).unwrap().collect_capped(10).unwrap();
+        ids.sort();
+        ids
+    };
+    let skipped = users.filter(args!(|user| user.get_field("active"), {default: false}));
+    assert_eq!(ids(skipped), vec![1]);
+    let included = users.filter(args!(|user| user.get_field("active"), {default: true}));
+    assert_eq!(ids(included), vec![1, 3]);
+    let raised = users.filter(args!(|user| user.get_field("active"), {default: r.error(args!())}))
+        .run::<Value>(conn)
+        .unwrap()
+        .collect_capped(10);
+    match raised {
+        Err(Error::Runtime(ref error)) => {
+            match **error {
+                RuntimeError::NonExistence(ref msg) => assert!(msg.contains("No attribute `active`"), "{}", msg),
+                ref error => panic!("expected a missing field, got {:?}", error),
+            }
+        }
+        res => panic!("expected a missing field, got {:?}", res),
+    }
+    drop_table(&r, conn, "reql_filter_default");
+}
+
+#[test]
+#[ignore]
+fn functions_of_two_and_three_parameters()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let numbers = r.expr(Json(vec![3, 1, 4, 1, 5]));
+    let sum = numbers.reduce(args!(|a, b| a.add(b))).run::<i64>(conn).unwrap().single().unwrap();
+    assert_eq!(sum, Some(14));
+    // Each step emits how much the accumulator changed and the row that changed it
+    let steps = numbers.fold(args!(0,
+                                   |acc, row| acc.add(row),
+                                   {emit: |old, row, new| vec![new.sub(old).eq(row)]}))
+        .run::<bool>(conn)
+        .unwrap()
+        .collect_capped(10)
+        .unwrap();
+    assert_eq!(steps, vec![true; 5]);
+}
+
+#[test]
+#[ignore]
+fn branch_maps_status_codes_to_labels()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let labels = r.expr(Json(vec![200, 404, 500, 302]))
+        .map(args!(|code| r.branch(args!(code.clone().eq(200), "ok",
+                                         code.clone().eq(404), "not found",
+                                         code.clone().eq(500), "error",
+                                         "unknown"))))
+        .run::<Vec<String>>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let expected = ["ok", "not found", "error", "unknown"].iter().map(|label| label.to_string()).collect();
+    assert_eq!(labels, Some(expected));
+}
+
+#[test]
+#[ignore]
+fn a_document_of_the_wrong_shape_keeps_its_raw_value()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    // `id` is a string and `title` is missing, so this isn't a `Post`
+    let stored = json!({"id": "first", "name": "not a post"});
+    let mut docs = r.expr(stored.clone()).run::<Post>(conn).unwrap().wait();
+    let doc = docs.next().unwrap().unwrap().unwrap();
+    assert!(doc.is_unexpected());
+    assert_eq!(doc.unexpected_value(), Some(&stored));
+    let post = r.expr(json!({"id": 1, "title": "a post"})).run::<Post>(conn).unwrap().wait().next();
+    let post = post.unwrap().unwrap().unwrap();
+    assert!(!post.is_unexpected());
+    assert_eq!(post.unexpected_value(), None);
+}
+
+// Answer every request with `ok`, after keeping it waiting for a while
+fn serve_slowly(listener: TcpListener, delay: Duration)
+{
+    for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        thread::spawn(move || {
+                          let lines = BufReader::new(stream.try_clone().unwrap()).lines().map(Result::unwrap);
+                          for line in lines {
+                              if line.is_empty() {
+                                  break;
+                              }
+                          }
+                          thread::sleep(delay);
+                          let _ = write!(stream,
+                                         "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\
+                                          Connection: close\r\n\r\nok");
+                      });
+    }
+}
+
+#[test]
+#[ignore]
+fn a_query_is_resubmitted_when_the_server_closes_its_session()
+{
+    let r = Client::new();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://127.0.0.1:{}/slow", listener.local_addr().unwrap().port());
+    thread::spawn(move || serve_slowly(listener, Duration::from_millis(500)));
+    let proxy = Proxy::start();
+    let conn = r.connect_sync(args!({servers: [proxy.addr.as_str()], discover_hosts: false})).unwrap();
+    let res = r.http(url.as_str()).run::<String>(conn).unwrap();
+    // The query has been written and the server is waiting for the page
+    thread::sleep(Duration::from_millis(200));
+    proxy.cut();
+    assert_eq!(res.single().unwrap(), Some(String::from("ok")));
+}
+
+#[test]
+#[ignore]
+fn ordered_feeds_include_the_offsets_of_their_changes()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let scores = create_table(&r, conn, "reql_offsets");
+    scores.index_create("score").run::<Value>(conn).unwrap().single().unwrap();
+    scores.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    scores.insert(Json(vec![json!({"id": 1, "score": 10}), json!({"id": 2, "score": 20})]))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let mut feed = scores.order_by(args!({index: r.desc("score")}))
+        .limit(2)
+        .changes()
+        .with_args(args!({include_offsets: true}))
+        .run::<Change<Value, Value>>(conn)
+        .unwrap()
+        .wait();
+    thread::sleep(Duration::from_millis(500));
+    // The new best score goes first and pushes the last one out of the window
+    scores.insert(json!({"id": 3, "score": 30})).run::<Value>(conn).unwrap().single().unwrap();
+    let change = match feed.next().unwrap().unwrap() {
+        Some(Document::Expected(change)) => change,
+        doc => panic!("expected a change, got {:?}", doc),
+    };
+    assert_eq!(change.new_offset, Some(0));
+    assert_eq!(change.new_val.unwrap()["id"], json!(3));
+    assert_eq!(change.old_offset, Some(1));
+    assert_eq!(change.old_val.unwrap()["id"], json!(1));
+    drop(feed);
+    drop_table(&r, conn, "reql_offsets");
+}
+
+#[test]
+#[ignore]
+fn connect_sync_needs_no_reactor_from_the_caller()
+{
+    let r = Client::new();
+    for _ in 0..2 {
+        // Each connection runs and stops a reactor of its own
+        let conn = r.connect_sync(args!({db: "rethinkdb"})).unwrap();
+        let servers = r.table("server_status").count().run::<u64>(conn).unwrap().single().unwrap();
+        assert!(servers.unwrap() >= 1);
+        conn.close();
+        assert!(r.expr(1).run::<i64>(conn).is_err());
+    }
+}
+
+#[test]
+#[ignore]
+fn max_with_an_index_returns_the_newest_row()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let events = create_table(&r, conn, "reql_max_index");
+    events.index_create("ts").run::<Value>(conn).unwrap().single().unwrap();
+    events.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    let rows = vec![json!({"id": 3, "ts": 100}), json!({"id": 1, "ts": 300}), json!({"id": 2, "ts": 200})];
+    events.insert(Json(rows)).run::<Value>(conn).unwrap().single().unwrap();
+    let newest = events.max(args!({index: "ts"})).run::<Value>(conn).unwrap().single().unwrap();
+    assert_eq!(newest, Some(json!({"id": 1, "ts": 300})));
+    let oldest = events.min(args!({index: "ts"})).get_field("id").run::<u64>(conn).unwrap().single().unwrap();
+    assert_eq!(oldest, Some(3));
+    drop_table(&r, conn, "reql_max_index");
+}
+
+#[test]
+#[ignore]
+fn reconnect_opens_fresh_sessions_for_the_same_connection()
+{
+    let r = Client::new();
+    let proxy = Proxy::start();
+    let conn = r.connect_sync(args!({servers: [proxy.addr.as_str()], discover_hosts: false})).unwrap();
+    assert_eq!(r.expr(1).run::<i64>(conn).unwrap().single().unwrap(), Some(1));
+    proxy.cut();
+    conn.reconnect().unwrap();
+    assert_eq!(r.expr(2).run::<i64>(conn).unwrap().single().unwrap(), Some(2));
+    // The cut left the proxy without clients so these are new sessions
+    assert!(!proxy.clients.lock().unwrap().is_empty());
+}
+
+#[test]
+#[ignore]
+fn collect_capped_stops_a_table_scan_at_the_cap()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let rows = create_table(&r, conn, "reql_capped");
+    rows.insert(r.range().with_args(100).map(args!(|id| args!({id: id}))))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let error = rows.run::<Value>(conn).unwrap().collect_capped(10).unwrap_err();
+    match error {
+        Error::Driver(ref error) => {
+            match **error {
+                DriverError::Other(ref msg) => assert_eq!(msg, "result exceeded 10 rows"),
+                ref error => panic!("expected the cap to be exceeded, got {:?}", error),
+            }
+        }
+        error => panic!("expected the cap to be exceeded, got {:?}", error),
+    }
+    assert_eq!(rows.limit(10).run::<Value>(conn).unwrap().collect_capped(10).unwrap().len(), 10);
+    drop_table(&r, conn, "reql_capped");
+}
+
+#[test]
+#[ignore]
+fn the_db_term_beats_the_run_option_which_beats_the_connection_default()
+{
+    let r = Client::new();
+    let setup = connect(&r);
+    for name in &["reql_connected", "reql_ran", "reql_chosen"] {
+        r.db_create(*name).run::<Value>(setup).unwrap().single().unwrap();
+        r.db(*name).table_create("places").run::<Value>(setup).unwrap().single().unwrap();
+        r.db(*name).table("places").insert(args!({id: 1, db: *name})).run::<Value>(setup).unwrap().single().unwrap();
+    }
+    let conn = r.connect_sync(args!({db: "reql_connected"})).unwrap();
+    assert_eq!(conn.default_db(), "reql_connected");
+    let db_of = |query: Client, arg: Arg| query.get_field("db").run::<String>(arg).unwrap().single().unwrap().unwrap();
+    let place = r.table("places").get(1);
+    assert_eq!(db_of(place.clone(), args!(conn)), "reql_connected");
+    assert_eq!(db_of(place.clone(), args!(conn, {db: "reql_ran"})), "reql_ran");
+    let chosen = r.db("reql_chosen").table("places").get(1);
+    assert_eq!(db_of(chosen, args!(conn, {db: "reql_ran"})), "reql_chosen");
+    for name in &["reql_connected", "reql_ran", "reql_chosen"] {
+        r.db_drop(*name).run::<Value>(setup).unwrap().single().unwrap();
+    }
+}
+
+#[test]
+#[ignore]
+fn get_path_reads_nested_fields_and_falls_back_to_a_default()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let user = r.expr(json!({"name": "alice", "address": {"city": {"name": "Nairobi"}}}));
+    let city = user.get_path(&["address", "city", "name"]).run::<String>(conn).unwrap().single().unwrap();
+    assert_eq!(city, Some(String::from("Nairobi")));
+    let zip = user.get_path(&["address", "postal", "code"])
+        .default("unknown")
+        .run::<String>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    assert_eq!(zip, Some(String::from("unknown")));
+}
+
+#[test]
+#[ignore]
+fn an_insert_too_large_for_the_server_is_refused_before_it_is_sent()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_too_large");
+    // 65 posts of a megabyte each are over the 64MB the server accepts
+    let body = "a".repeat(1024 * 1024);
+    let docs: Vec<Value> = (0..65).map(|id| json!({"id": id, "body": body})).collect();
+    let error = posts.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap_err();
+    assert!(format!("{}", error).contains("query too large"), "{}", error);
+    let count = posts.count().run::<u64>(conn).unwrap().single().unwrap();
+    assert_eq!(count, Some(0));
+    drop_table(&r, conn, "reql_too_large");
+}
+
+#[test]
+#[ignore]
+fn js_evaluates_an_expression_on_the_server()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let sum = r.js("[1, 2, 3].reduce(function(a, b) { return a + b; })").run::<i64>(conn).unwrap().single().unwrap();
+    assert_eq!(sum, Some(6));
+    let greeting = r.js(args!("'hello ' + 'world'", {timeout: 1.3})).run::<String>(conn).unwrap().single().unwrap();
+    assert_eq!(greeting, Some(String::from("hello world")));
+}
+
+#[test]
+#[ignore]
+fn filter_ok_keeps_the_rows_matching_a_client_side_predicate()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let titles: Vec<String> = r.expr(Json(vec![json!({"id": 1, "title": "Rust"}),
+                                                json!({"id": 2, "title": "Go"}),
+                                                json!({"id": 3, "title": "Ruby"})]))
+        .run::<Post>(conn)
+        .unwrap()
+        .filter_ok(|post| post.title.starts_with('R'))
+        .wait()
+        .filter_map(|doc| match doc.unwrap() {
+                        Some(Document::Expected(post)) => Some(post.title),
+                        _ => None,
+                    })
+        .collect();
+    assert_eq!(titles, vec!["Rust", "Ruby"]);
+}
+
+#[test]
+#[ignore]
+fn counting_with_the_primary_index_matches_a_plain_count()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let rows = create_table(&r, conn, "reql_count_index");
+    rows.insert(r.range().with_args(25).map(args!(|id| args!({id: id}))))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let scanned = rows.count().run::<u64>(conn).unwrap().single().unwrap();
+    let indexed = rows.count().with_args(args!({index: "id"})).run::<u64>(conn).unwrap().single().unwrap();
+    assert_eq!(scanned, Some(25));
+    assert_eq!(indexed, scanned);
+    drop_table(&r, conn, "reql_count_index");
+}
+
+#[test]
+#[ignore]
+fn connect_addrs_uses_the_addresses_as_they_are()
+{
+    let r = Client::new();
+    let core = Core::new().unwrap();
+    let addr = "127.0.0.1:28015".parse().unwrap();
+    let conn = r.connect_addrs(vec![addr], args!(&core.handle(), {discover_hosts: false})).unwrap();
+    let names: Vec<String> = conn.cluster().into_iter().map(|server| server.name).collect();
+    assert_eq!(names, vec!["127.0.0.1:28015"]);
+    assert_eq!(r.expr(1).run::<i64>(conn).unwrap().single().unwrap(), Some(1));
+}
+
+#[test]
+#[ignore]
+fn union_interleaves_two_ordered_selections_by_a_field()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_union_posts");
+    let comments = create_table(&r, conn, "reql_union_comments");
+    for table in &[&posts, &comments] {
+        table.index_create("ts").run::<Value>(conn).unwrap().single().unwrap();
+        table.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let rows = |times: &[u64]| -> Vec<Value> { times.iter().map(|ts| json!({"id": ts, "ts": ts})).collect() };
+    posts.insert(Json(rows(&[1, 4, 5, 9]))).run::<Value>(conn).unwrap().single().unwrap();
+    comments.insert(Json(rows(&[2, 3, 7, 8]))).run::<Value>(conn).unwrap().single().unwrap();
+    let times = posts.order_by(args!({index: "ts"}))
+        .union(args!(comments.order_by(args!({index: "ts"})), {interleave: "ts"}))
+        .get_field("ts")
+        .run::<u64>(conn)
+        .unwrap()
+        .collect_capped(10)
+        .unwrap();
+    assert_eq!(times, vec![1, 2, 3, 4, 5, 7, 8, 9]);
+    drop_table(&r, conn, "reql_union_posts");
+    drop_table(&r, conn, "reql_union_comments");
+}
+
+#[test]
+#[ignore]
+fn merge_adds_the_author_of_each_post()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_merge_users");
+    users.insert(Json(vec![json!({"id": "u1", "name": "alice"}), json!({"id": "u2", "name": "bob"})]))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    let posts = r.expr(Json(vec![json!({"id": 1, "author_id": "u2"}), json!({"id": 2, "author_id": "u1"})]));
+    let authors = posts.merge(args!(|post| args!({author: users.get(post.get_field("author_id"))})))
+        .map(args!(|post| vec![post.get_field("id"), post.get_field("author").get_field("name")]))
+        .run::<Value>(conn)
+        .unwrap()
+        .into_map::<u64, String>()
+        .unwrap();
+    assert_eq!(authors[&1], "bob");
+    assert_eq!(authors[&2], "alice");
+    drop_table(&r, conn, "reql_merge_users");
+}
+
+#[test]
+#[ignore]
+fn range_counts_up_to_its_bound()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let numbers = |range: Client| range.run::<u64>(conn).unwrap().collect_capped(10).unwrap();
+    assert_eq!(numbers(r.range().with_args(args!(5))), vec![0, 1, 2, 3, 4]);
+    assert_eq!(numbers(r.range().limit(3)), vec![0, 1, 2]);
+    assert_eq!(numbers(r.range().with_args(args!(2, 5))), vec![2, 3, 4]);
+    assert!(r.range().run::<u64>(conn).is_err());
+}
+
+// A number as a term, built without the command builder
+fn number(n: f64) -> Term
+{
+    let mut datum = Datum::new();
+    datum.set_field_type(DT::R_NUM);
+    datum.set_r_num(n);
+    let mut term = Term::new();
+    term.set_field_type(TT::DATUM);
+    term.set_datum(datum);
+    term
+}
+
+#[test]
+#[ignore]
+fn run_term_runs_a_term_built_by_hand()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let mut add = Term::new();
+    add.set_field_type(TT::ADD);
+    add.mut_args().push(number(40.0));
+    add.mut_args().push(number(2.0));
+    let answer = r.run_term::<_, i64>(conn, add).unwrap().single().unwrap();
+    assert_eq!(answer, Some(42));
+}
+
+#[test]
+#[ignore]
+fn ensure_db_creates_the_missing_default_database()
+{
+    let r = Client::new();
+    let setup = connect(&r);
+    let listed = |conn: Connection| r.db_list().run::<Vec<String>>(conn).unwrap().single().unwrap().unwrap();
+    assert!(!listed(setup).contains(&String::from("reql_ensured")));
+    let conn = r.connect_sync(args!({db: "reql_ensured", ensure_db: true})).unwrap();
+    assert!(listed(setup).contains(&String::from("reql_ensured")));
+    // Queries run against it right away
+    r.table_create("posts").run::<Value>(conn).unwrap().single().unwrap();
+    // Connecting again finds it there
+    r.connect_sync(args!({db: "reql_ensured", ensure_db: true})).unwrap();
+    r.db_drop("reql_ensured").run::<Value>(setup).unwrap().single().unwrap();
+}
+
+#[test]
+#[ignore]
+fn delete_returns_the_documents_it_removed()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_delete_changes");
+    let docs: Vec<Value> = (1..6).map(|id| json!({"id": id, "title": format!("post {}", id)})).collect();
+    posts.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap();
+    let res = posts.filter(args!(|post| post.get_field("id").gt(3)))
+        .delete()
+        .with_args(args!({return_changes: true}))
+        .run::<WriteResult<Post>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.deleted, 2);
+    assert_eq!(res.errors, 0);
+    let mut removed: Vec<Post> = res.changes.unwrap().into_iter().filter_map(|change| change.old_val).collect();
+    removed.sort_by_key(|post| post.id);
+    assert_eq!(removed,
+               vec![Post { id: 4, title: String::from("post 4") },
+                    Post { id: 5, title: String::from("post 5") }]);
+    assert_eq!(posts.count().run::<u64>(conn).unwrap().single().unwrap(), Some(3));
+    drop_table(&r, conn, "reql_delete_changes");
+}
+
+#[test]
+#[ignore]
+fn every_now_in_a_query_is_the_same_time()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let same = r.now().eq(r.now()).run::<bool>(conn).unwrap().single().unwrap();
+    assert_eq!(same, Some(true));
+    let elapsed = r.now().sub(r.now()).run::<f64>(conn).unwrap().single().unwrap();
+    assert_eq!(elapsed, Some(0.0));
+}
+
+#[test]
+#[ignore]
+fn a_point_feed_sees_the_insert_then_the_update()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_point_feed");
+    let mut feed = users.get("alice").changes().run::<Change<Value, Value>>(conn).unwrap().wait();
+    thread::sleep(Duration::from_millis(500));
+    {
+        let mut next = || match feed.next().unwrap().unwrap() {
+            Some(Document::Expected(change)) => change,
+            doc => panic!("expected a change, got {:?}", doc),
+        };
+        users.insert(json!({"id": "alice", "visits": 1})).run::<Value>(conn).unwrap().single().unwrap();
+        let inserted = next();
+        assert_eq!(inserted.old_val, None);
+        assert_eq!(inserted.new_val.unwrap()["visits"], json!(1));
+        users.get("alice").update(args!({visits: 2})).run::<Value>(conn).unwrap().single().unwrap();
+        let updated = next();
+        assert_eq!(updated.old_val.unwrap()["visits"], json!(1));
+        assert_eq!(updated.new_val.unwrap()["visits"], json!(2));
+    }
+    drop(feed);
+    drop_table(&r, conn, "reql_point_feed");
+}
+
+#[test]
+#[ignore]
+fn the_server_version_is_known_once_a_session_is_open()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    r.expr(1).run::<u32>(conn).unwrap().single().unwrap();
+    let version = conn.server_version().expect("a session has been opened");
+    assert!(version.starts_with("2."), "{}", version);
+}
+
+#[test]
+#[ignore]
+fn seeded_samples_pick_the_same_documents_every_time()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let heroes = create_table(&r, conn, "reql_sample_seeded");
+    let docs: Vec<Value> = (0..20).map(|id| json!({"id": id})).collect();
+    heroes.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap();
+    let sample = |seed| {
+        let docs = heroes.sample_seeded(5, seed).run::<Vec<Value>>(conn).unwrap().single().unwrap().unwrap();
+        docs.iter().map(|doc| doc["id"].as_u64().unwrap()).collect::<Vec<_>>()
+    };
+    let first = sample("spider");
+    assert_eq!(first.len(), 5);
+    assert_eq!(first, sample("spider"));
+    drop_table(&r, conn, "reql_sample_seeded");
+}
+
+// Records the servers it's told about
+#[derive(Default)]
+struct Servers
+{
+    up: Mutex<Vec<String>>,
+    down: Mutex<Vec<String>>,
+}
+
+impl ConnectionListener for Servers
+{
+    fn on_server_up(&self, name: &str)
+    {
+        self.up.lock().unwrap().push(name.to_string());
+    }
+
+    fn on_server_down(&self, name: &str)
+    {
+        self.down.lock().unwrap().push(name.to_string());
+    }
+}
+
+fn seen(names: &Mutex<Vec<String>>, name: &str) -> bool
+{
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Instant::now() < deadline {
+        if names.lock().unwrap().iter().any(|seen| seen == name) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+// Needs the `rethinkdb` binary to start a second server joining the first
+#[test]
+#[ignore]
+fn the_listener_is_told_when_a_server_goes_away()
+{
+    let r = Client::new();
+    let servers = Arc::new(Servers::default());
+    let listener: Arc<ConnectionListener> = servers.clone();
+    let _conn = r.connect_sync(args!(listener)).unwrap();
+    let name = "reql_listener_test";
+    let dir = std::env::temp_dir().join(name);
+    let mut server = Command::new("rethinkdb")
+        .args(&["--port-offset", "1", "--join", "localhost:29015", "--server-name", name, "--directory"])
+        .arg(&dir)
+        .spawn()
+        .unwrap();
+    assert!(seen(&servers.up, name), "the server never joined");
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(seen(&servers.down, name), "the server was never reported down");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[derive(Debug, Deserialize)]
+struct Place
+{
+    id: u32,
+}
+
+#[test]
+#[ignore]
+fn get_nearest_returns_the_closest_points_within_the_distance()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let places = create_table(&r, conn, "reql_nearest");
+    places.index_create(args!("loc", {geo: true})).run::<Value>(conn).unwrap().single().unwrap();
+    places.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    // Roughly 70m, 700m, 2km and 70km east of the origin
+    for &(id, lon) in &[(1, 2.351), (2, 2.36), (3, 2.38), (4, 3.35)] {
+        places.insert(args!({id: id, loc: r.point(args!(lon, 48.85))})).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let nearest = |max_results| {
+        let found = places.get_nearest(args!(r.point(args!(2.35, 48.85)),
+                                             {index: "loc", max_results: max_results, max_dist: 5000, unit: "m"}))
+            .run::<Vec<Nearest<Place>>>(conn)
+            .unwrap()
+            .single()
+            .unwrap()
+            .unwrap();
+        assert!(found.iter().all(|near| near.dist < 5000.0));
+        found.iter().map(|near| near.doc.id).collect::<Vec<_>>()
+    };
+    assert_eq!(nearest(10), vec![1, 2, 3]);
+    assert_eq!(nearest(2), vec![1, 2]);
+    drop_table(&r, conn, "reql_nearest");
+}
+
+#[test]
+#[ignore]
+fn between_ranges_within_one_category_of_a_compound_index()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_compound_between");
+    posts.index_create(args!("cat_ts", |post| vec![post.get_field("cat"), post.get_field("ts")]))
+        .run::<Value>(conn)
+        .unwrap()
+        .single()
+        .unwrap();
+    posts.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    let docs = vec![json!({"id": 1, "cat": "books", "ts": 5}),
+                    json!({"id": 2, "cat": "books", "ts": 15}),
+                    json!({"id": 3, "cat": "books", "ts": 25}),
+                    json!({"id": 4, "cat": "films", "ts": 15})];
+    posts.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap();
+    let found = posts.between(args!(["books", 10], ["books", 30], {index: "cat_ts"}))
+        .order_by(args!({index: "cat_ts"}))
+        .run::<Vec<Value>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    let ids: Vec<_> = found.iter().map(|post| post["id"].clone()).collect();
+    assert_eq!(ids, vec![json!(2), json!(3)]);
+    drop_table(&r, conn, "reql_compound_between");
+}
+
+#[test]
+#[ignore]
+fn with_fields_drops_documents_missing_a_field()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_with_fields");
+    let docs = vec![json!({"id": 1, "name": "alice", "contact": {"phone": {"work": "555-1234", "home": "555-0000"}}}),
+                    json!({"id": 2, "name": "bob", "contact": {"phone": {"home": "555-9876"}}}),
+                    json!({"id": 3, "contact": {"phone": {"work": "555-4321"}}})];
+    users.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap();
+    let found = users.with_fields(args!("id", "name", {contact: {phone: "work"}}))
+        .run::<Vec<Value>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert_eq!(found,
+               vec![json!({"id": 1, "name": "alice", "contact": {"phone": {"work": "555-1234"}}})]);
+    drop_table(&r, conn, "reql_with_fields");
+}
+
+#[test]
+#[ignore]
+fn connect_works_for_a_user_who_cannot_read_the_servers()
+{
+    let r = Client::new();
+    let admin = connect(&r);
+    let users = r.db("rethinkdb").table("users");
+    users.insert(args!({id: "reql_limited", password: "secret"})).run::<Value>(admin).unwrap().single().unwrap();
+    r.db("test")
+        .grant()
+        .with_args(args!("reql_limited", Json(json!({"read": true}))))
+        .run::<Value>(admin)
+        .unwrap()
+        .single()
+        .unwrap();
+    let conn = r.connect_sync(args!({user: "reql_limited", password: "secret", watch_servers: false})).unwrap();
+    assert!(r.db("rethinkdb").table("server_status").run::<Value>(conn).unwrap().single().is_err());
+    let tables = r.db("test").table_list().run::<Vec<String>>(conn).unwrap().single().unwrap();
+    assert!(tables.is_some());
+    users.get("reql_limited").delete().run::<Value>(admin).unwrap().single().unwrap();
+}
+
+#[test]
+#[ignore]
+fn during_includes_the_start_of_a_half_open_window_but_not_its_end()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let events = create_table(&r, conn, "reql_during");
+    let times = ["2017-06-01T00:00:00Z", "2017-06-15T12:00:00Z", "2017-07-01T00:00:00Z"];
+    for (id, time) in times.iter().enumerate() {
+        events.insert(args!({id: id, ts: r.iso8601(*time)})).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    let end = r.iso8601(times[2]).run::<DateTime>(conn).unwrap().single().unwrap().unwrap();
+    let start = r.iso8601(times[0]);
+    let found = events.filter(args!(|event| {
+                                         let ts = event.get_field("ts");
+                                         ts.during(args!(start, end, {left_bound: "closed", right_bound: "open"}))
+                                     }))
+        .order_by("id")
+        .run::<Vec<Value>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    let ids: Vec<_> = found.iter().map(|event| event["id"].clone()).collect();
+    assert_eq!(ids, vec![json!(0), json!(1)]);
+    drop_table(&r, conn, "reql_during");
+}
+
+#[test]
+#[ignore]
+fn reduce_returns_its_default_for_an_empty_table()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_reduce_default");
+    let total = || {
+        posts.map(args!(|post| post.get_field("views")))
+            .reduce(args!(|a, b| a.add(b), {default: 0}))
+            .run::<u64>(conn)
+            .unwrap()
+            .single()
+            .unwrap()
+    };
+    assert_eq!(total(), Some(0));
+    posts.insert(Json(vec![json!({"views": 3}), json!({"views": 4})])).run::<Value>(conn).unwrap().single().unwrap();
+    assert_eq!(total(), Some(7));
+    drop_table(&r, conn, "reql_reduce_default");
+}
+
+#[test]
+#[ignore]
+fn replace_with_now_runs_once_it_is_non_atomic()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_non_atomic");
+    posts.insert(args!({id: 1, title: "draft"})).run::<Value>(conn).unwrap().single().unwrap();
+    let post = posts.get(1);
+    let res = post.replace(args!(|post| post.merge(args!({edited_at: r.now()})), {non_atomic: true}))
+        .run::<WriteResult<Value>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.replaced, 1);
+    let edited = post.has_fields("edited_at").run::<bool>(conn).unwrap().single().unwrap();
+    assert_eq!(edited, Some(true));
+    drop_table(&r, conn, "reql_non_atomic");
+}
+
+#[test]
+#[ignore]
+fn a_profiled_query_returns_its_rows_with_the_profile()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let (rows, profile) = r.range().with_args(3)
+        .map(args!(|n| n.mul(2)))
+        .run::<u32>(args!(conn, {profile: true}))
+        .unwrap()
+        .into_vec_with_profile()
+        .unwrap();
+    assert_eq!(rows, vec![0, 2, 4]);
+    match profile {
+        Some(Value::Array(ref steps)) if !steps.is_empty() => {}
+        profile => panic!("expected the steps of the query, got {:?}", profile),
+    }
+}
+
+#[test]
+#[ignore]
+fn upserting_a_document_twice_updates_it_the_second_time()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_upsert");
+    let upsert = |email| {
+        users.upsert(args!({id: "bob", email: email}))
+            .run::<WriteResult<Value>>(conn)
+            .unwrap()
+            .single()
+            .unwrap()
+            .unwrap()
+    };
+    let first = upsert("bob@example.com");
+    assert_eq!((first.inserted, first.replaced, first.errors), (1, 0, 0));
+    let second = upsert("bob@example.org");
+    assert_eq!((second.inserted, second.replaced, second.errors), (0, 1, 0));
+    let email = users.get("bob").get_field("email").run::<String>(conn).unwrap().single().unwrap();
+    assert_eq!(email, Some(String::from("bob@example.org")));
+    drop_table(&r, conn, "reql_upsert");
+}
+
+#[test]
+#[ignore]
+fn a_lagging_feed_drops_its_oldest_changes_and_counts_them()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let events = create_table(&r, conn, "reql_drop_oldest");
+    let mut feed = events.changes()
+        .with_args(args!({changefeed_queue_size: 100}))
+        .run::<Change<Value, Value>>(args!(conn, {buffer_size: 2, overflow: "drop_oldest"}))
+        .unwrap();
+    thread::sleep(Duration::from_millis(500));
+    for id in 1..6 {
+        events.insert(args!({id: id})).run::<Value>(conn).unwrap().single().unwrap();
+    }
+    // Fall behind until every change has arrived
+    thread::sleep(Duration::from_millis(500));
+    let ids: Vec<_> = (&mut feed)
+        .wait()
+        .take(2)
+        .map(|change| match change.unwrap() {
+                 Some(Document::Expected(change)) => change.new_val.unwrap()["id"].clone(),
+                 doc => panic!("expected a change, got {:?}", doc),
+             })
+        .collect();
+    assert_eq!(ids, vec![json!(4), json!(5)]);
+    assert_eq!(feed.dropped(), 3);
+    drop(feed);
+    drop_table(&r, conn, "reql_drop_oldest");
+}
+
+#[test]
+#[ignore]
+fn a_uuid_derived_from_a_name_finds_the_document_it_keys()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_uuid_of");
+    let email = "slava@example.com";
+    let uuid = || r.uuid_of(email).run::<String>(conn).unwrap().single().unwrap().unwrap();
+    let id = uuid();
+    assert_eq!(id, uuid());
+    // The one the server documents for this name
+    assert_eq!(id, "90691cbc-b5ea-5826-ae98-951e30fc3b2d");
+    users.insert(args!({id: r.uuid_of(email), email: email})).run::<Value>(conn).unwrap().single().unwrap();
+    let user = users.get(id.as_str()).run::<Value>(conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(user["email"], json!(email));
+    let found = users.get(r.uuid_of(email)).get_field("email").run::<String>(conn).unwrap().single().unwrap();
+    assert_eq!(found, Some(email.to_string()));
+    drop_table(&r, conn, "reql_uuid_of");
+}
+
+#[test]
+#[ignore]
+fn tables_lists_a_table_just_created()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    create_table(&r, conn, "reql_listed");
+    let tables = r.db("test").tables(conn).unwrap();
+    assert!(tables.contains(&String::from("reql_listed")), "{:?}", tables);
+    let mut sorted = tables.clone();
+    sorted.sort();
+    assert_eq!(tables, sorted);
+    assert!(r.databases(conn).unwrap().contains(&String::from("test")));
+    drop_table(&r, conn, "reql_listed");
+}
+
+#[test]
+#[ignore]
+fn a_raised_array_limit_lets_a_large_array_through()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let array = r.range().with_args(200000).coerce_to("array").count();
+    assert!(array.run::<u64>(conn).unwrap().single().is_err());
+    let count = array.run::<u64>(args!(conn, {array_limit: 300000})).unwrap().single().unwrap();
+    assert_eq!(count, Some(200000));
+}
+
+#[test]
+#[ignore]
+fn split_initial_hands_over_the_snapshot_then_the_live_changes()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_split_initial");
+    posts.insert(Json(vec![json!({"id": 1}), json!({"id": 2})])).run::<Value>(conn).unwrap().single().unwrap();
+    let (mut initial, feed) = posts.changes()
+        .with_args(args!({include_initial: true, include_states: true}))
+        .run::<Change<Value, Value>>(conn)
+        .unwrap()
+        .split_initial()
+        .unwrap();
+    initial.sort_by_key(|post| post["id"].as_u64());
+    assert_eq!(initial, vec![json!({"id": 1}), json!({"id": 2})]);
+    posts.insert(json!({"id": 3})).run::<Value>(conn).unwrap().single().unwrap();
+    let mut feed = feed.wait();
+    match feed.next().unwrap().unwrap() {
+        Some(Document::Expected(change)) => assert_eq!(change.new_val, Some(json!({"id": 3}))),
+        doc => panic!("expected a change, got {:?}", doc),
+    }
+    drop(feed);
+    drop_table(&r, conn, "reql_split_initial");
+}
+
+#[test]
+#[ignore]
+fn exists_looks_a_value_up_in_an_index()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let users = create_table(&r, conn, "reql_exists");
+    users.index_create("email").run::<Value>(conn).unwrap().single().unwrap();
+    users.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    users.insert(args!({id: 1, email: "alice@example.com"})).run::<Value>(conn).unwrap().single().unwrap();
+    assert!(users.exists(conn, "email", "alice@example.com").unwrap());
+    assert!(!users.exists(conn, "email", "bob@example.com").unwrap());
+    // The primary key isn't looked up in place of the index
+    assert!(!users.exists(conn, "email", 1).unwrap());
+    drop_table(&r, conn, "reql_exists");
+}
+
+#[test]
+#[ignore]
+fn info_reports_the_primary_key_and_the_indexes_of_a_table()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    r.db("test").table_create(args!("reql_info", {primary_key: "email"})).run::<Value>(conn).unwrap().single().unwrap();
+    let users = r.db("test").table("reql_info");
+    users.index_create("team").run::<Value>(conn).unwrap().single().unwrap();
+    let info = users.info().run::<ObjectInfo>(conn).unwrap().single().unwrap().unwrap();
+    assert_eq!(info.name, "reql_info");
+    assert_eq!(info.object_type, "TABLE");
+    assert_eq!(info.primary_key, Some(String::from("email")));
+    assert_eq!(info.indexes, Some(vec![String::from("team")]));
+    assert_eq!(info.db.map(|db| db.name), Some(String::from("test")));
+    drop_table(&r, conn, "reql_info");
+}
+
+#[test]
+#[ignore]
+fn soft_batches_are_synced_once_they_are_all_in()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let posts = create_table(&r, conn, "reql_batched_sync");
+    let docs = (0..1000).map(|id| json!({"id": id}));
+    let status = posts.insert_batched(args!(conn, {durability: "soft", sync_on_complete: true}), docs, 100)
+        .unwrap();
+    assert_eq!(status.write.inserted, 1000);
+    assert!(status.synced);
+    assert_eq!(status.sync_error, None);
+    let count = posts.count().run::<u64>(conn).unwrap().single().unwrap();
+    assert_eq!(count, Some(1000));
+    drop_table(&r, conn, "reql_batched_sync");
+}
+
+#[test]
+#[ignore]
+fn coerce_to_converts_what_it_can_and_reports_what_it_cannot()
+{
+    let r = Client::new();
+    let conn = connect(&r);
+    let number = r.expr("42").coerce_to("number").run::<f64>(conn).unwrap().single().unwrap();
+    assert_eq!(number, Some(42.0));
+    let string = r.expr(42).coerce_to("string").run::<String>(conn).unwrap().single().unwrap();
+    assert_eq!(string, Some(String::from("42")));
+    let pairs = r.expr(args!({a: 1})).coerce_to("array").run::<Value>(conn).unwrap().single().unwrap();
+    assert_eq!(pairs, Some(json!([["a", 1]])));
+    match r.expr("abc").coerce_to("number").run::<f64>(conn).unwrap().single() {
+        Err(Error::Runtime(ref error)) => {
+            match **error {
+                RuntimeError::QueryLogic(ref msg) => assert!(msg.contains("Could not coerce"), "{}", msg),
+                ref error => panic!("expected a query logic error, got {:?}", error),
+            }
+        }
+        res => panic!("expected a query logic error, got {:?}", res),
+    }
+}
+
+#[test]
+#[ignore]
+fn deleting_an_index_range_leaves_the_rows_outside_it()
+{
+    let r = Client::new();
+    let conn = r.connect_sync(args!({safe_mode: true})).unwrap();
+    let events = create_table(&r, conn, "reql_between_delete");
+    events.index_create("ts").run::<Value>(conn).unwrap().single().unwrap();
+    events.index_wait().run::<Value>(conn).unwrap().single().unwrap();
+    let docs: Vec<Value> = (0..10).map(|id| json!({"id": id, "ts": id * 100})).collect();
+    events.insert(Json(docs)).run::<Value>(conn).unwrap().single().unwrap();
+    // Everything before the cutoff, which isn't included
+    let res = events.between(args!(r.minval(), 400, {index: "ts"}))
+        .delete()
+        .run::<WriteResult<Value>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.deleted, 4);
+    let left = events.order_by(args!({index: "ts"}))
+        .get_field("ts")
+        .run::<Vec<u64>>(conn)
+        .unwrap()
+        .single()
+        .unwrap()
+        .unwrap();
+    assert_eq!(left, vec![400, 500, 600, 700, 800, 900]);
+    drop_table(&r, conn, "reql_between_delete");
+}
+
+#[test]
+#[ignore]
+fn an_idle_feed_finds_out_its_server_went_silent()
+{
+    let r = Client::new();
+    let setup = connect(&r);
+    let events = create_table(&r, setup, "reql_idle_feed");
+    let proxy = Proxy::start();
+    let conn = r.connect_sync(args!({servers: [proxy.addr.as_str()], discover_hosts: false})).unwrap();
+    let mut feed = events.changes().run::<Value>(args!(conn, {idle_timeout: 500})).unwrap().wait();
+    thread::sleep(Duration::from_millis(500));
+    events.insert(args!({id: 1})).run::<Value>(setup).unwrap().single().unwrap();
+    assert!(feed.next().unwrap().is_ok());
+    // Quiet but alive, so the check is answered and the feed carries on
+    thread::sleep(Duration::from_millis(1500));
+    events.insert(args!({id: 2})).run::<Value>(setup).unwrap().single().unwrap();
+    assert!(feed.next().unwrap().is_ok());
+    proxy.freeze();
+    let start = Instant::now();
+    match feed.next().unwrap() {
+        Err(Error::Driver(ref error)) => {
+            match **error {
+                DriverError::ServerClosed(_) => {}
+                ref error => panic!("expected the server to be closed, got {:?}", error),
+            }
+        }
+        res => panic!("expected the server to be closed, got {:?}", res),
+    }
+    // One idle timeout before the check and one more for its answer
+    assert!(start.elapsed() < Duration::from_secs(3), "{:?}", start.elapsed());
+    drop(feed);
+    drop_table(&r, setup, "reql_idle_feed");
+}
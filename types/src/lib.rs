@@ -11,6 +11,7 @@ use std::net::IpAddr;
 use std::collections::HashMap;
 use std::ops::Deref;
 
+use chrono::Timelike;
 use serde_json::Value;
 use uuid::Uuid;
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
@@ -19,7 +20,7 @@ use serde::{Serialize, Deserialize, Serializer, Deserializer};
 pub struct DateTime(chrono::DateTime<chrono::UTC>);
 
 /// Status returned by a write command
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct WriteStatus {
     pub inserted: u32,
     pub replaced: u32,
@@ -33,6 +34,53 @@ pub struct WriteStatus {
     pub changes: Option<Value>,
 }
 
+/// Status returned by a batched insert
+#[derive(Debug, Clone, Default)]
+pub struct BatchStatus {
+    /// The writes of all the batches added up
+    pub write: WriteStatus,
+    /// Whether the table was synced once the batches were done
+    pub synced: bool,
+    /// Why syncing the table failed, if it did
+    pub sync_error: Option<String>,
+}
+
+/// Status returned by a write command, with typed changes
+///
+/// Unlike `WriteStatus`, the changes returned by `insert`, `update`,
+/// `replace` or `delete` when they are run with `return_changes: true` are
+/// deserialized into `Change<T, T>`, so the old and new values of the
+/// documents written can be used directly.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WriteResult<T> {
+    pub inserted: u32,
+    pub replaced: u32,
+    pub unchanged: u32,
+    pub skipped: u32,
+    pub deleted: u32,
+    pub errors: u32,
+    pub first_error: Option<String>,
+    pub generated_keys: Option<Vec<Uuid>>,
+    pub warnings: Option<Vec<String>>,
+    pub changes: Option<Vec<Change<T, T>>>,
+}
+
+/// Structure of data returned by `info` on a database or a table
+///
+/// Only tables have a `db`, a `primary_key`, `indexes` and
+/// `doc_count_estimates`, one for each shard.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObjectInfo {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub db: Option<Box<ObjectInfo>>,
+    pub primary_key: Option<String>,
+    pub indexes: Option<Vec<String>>,
+    pub doc_count_estimates: Option<Vec<u64>>,
+}
+
 /// Structure of data in `cluster_config` table
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ClusterConfig {
@@ -125,17 +173,47 @@ pub struct TableStatus {
 pub struct User {
 }
 
+/// Structure of a document returned by a changefeed
 #[derive(Deserialize, Debug, Clone)]
 pub struct Change<O, N> {
     pub old_val: Option<O>,
     pub new_val: Option<N>,
     #[serde(rename = "type")]
     pub result_type: Option<String>,
+    /// Position `old_val` had in an `order_by(...).limit(n)` feed
+    ///
+    /// The offsets are only set when the feed is opened with
+    /// `changes(args!({include_offsets: true}))`. Together they are enough
+    /// to keep a local copy of the ordered window, such as a top-N
+    /// leaderboard, in sync without querying it again.
     pub old_offset: Option<usize>,
+    /// Position `new_val` has in an `order_by(...).limit(n)` feed
     pub new_offset: Option<usize>,
     pub state: Option<String>,
 }
 
+/// Structure of a document returned by a join
+///
+/// Joins like `eq_join` return the matching documents side by side. Call `zip`
+/// on the join to merge them into a single document instead, which can then
+/// be deserialized straight into a flat type. When both sides have a field with
+/// the same name, the one from the `right` document wins, just like in RethinkDB.
+/// Use `without` after `zip` to drop fields you don't need, such as the join key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Join<L, R> {
+    pub left: L,
+    pub right: R,
+}
+
+/// Structure of a document returned by `get_nearest`
+///
+/// `dist` is in the `unit` given to the query, which defaults to meters.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Nearest<T> {
+    pub dist: f64,
+    pub doc: T,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct Time {
     #[serde(rename = "$reql_type$")]
@@ -160,10 +238,24 @@ impl<'de> Deserialize<'de> for DateTime {
 }
 
 impl Serialize for DateTime {
-    fn serialize<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        unimplemented!();
+        // Sent as a `TIME` pseudotype, truncated to the millisecond
+        // precision RethinkDB supports
+        let msecs = self.0.nanosecond() / 1_000_000;
+        let time = Time {
+            reql_type: String::from("TIME"),
+            epoch_time: self.0.timestamp() as f64 + msecs as f64 / 1000.0,
+            timezone: String::from("+00:00"),
+        };
+        time.serialize(serializer)
+    }
+}
+
+impl From<chrono::DateTime<chrono::UTC>> for DateTime {
+    fn from(dt: chrono::DateTime<chrono::UTC>) -> DateTime {
+        DateTime(dt)
     }
 }
 